@@ -0,0 +1,157 @@
+//! Draw a triangle with `OffscreenContext` into an image owned by the
+//! caller, as a host engine would, then save it as `offscreen.png`.
+
+use std::sync::Arc;
+
+use image::RgbaImage;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        CopyImageToBufferInfo, PrimaryCommandBufferAbstract,
+    },
+    device::{Device, DeviceCreateInfo, QueueCreateInfo, QueueFlags},
+    format::Format,
+    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
+    instance::{Instance, InstanceCreateFlags, InstanceCreateInfo, InstanceExtensions},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    sync::GpuFuture,
+    VulkanLibrary,
+};
+
+use radiance_cascades::{
+    drawing::{context::BlendMode, offscreen::OffscreenContext, vulkan_helper::VulkanState},
+    geometry::{Vertex2D, Vertex2DBuilder},
+};
+
+const EXTENT: [u32; 2] = [512, 512];
+
+fn main() {
+    let library = VulkanLibrary::new().expect("Vulkan library not found");
+    let portability = library.supported_extensions().khr_portability_enumeration;
+    let instance = Instance::new(
+        library,
+        InstanceCreateInfo {
+            flags: if portability {
+                InstanceCreateFlags::ENUMERATE_PORTABILITY
+            } else {
+                InstanceCreateFlags::empty()
+            },
+            enabled_extensions: InstanceExtensions {
+                khr_portability_enumeration: portability,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .expect("Can't create instance");
+
+    // Stands for the device created by the host engine.
+    let (physical_device, queue_family_index) = instance
+        .enumerate_physical_devices()
+        .expect("Can't enumerate devices")
+        .find_map(|device| {
+            let idx = device
+                .queue_family_properties()
+                .iter()
+                .position(|family| family.queue_flags.contains(QueueFlags::GRAPHICS))?;
+            Some((device, idx as u32))
+        })
+        .expect("No device with a graphics queue");
+    let (device, mut queues) = Device::new(
+        physical_device,
+        DeviceCreateInfo {
+            queue_create_infos: vec![QueueCreateInfo {
+                queue_family_index,
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+    )
+    .expect("Can't create device");
+    let vk_state = VulkanState::from_device(device.clone(), queues.next().unwrap());
+
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    let target = Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R8G8B8A8_SRGB,
+            extent: [EXTENT[0], EXTENT[1], 1],
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .expect("Can't create target image");
+    let ctx = OffscreenContext::new(
+        &vk_state,
+        ImageView::new_default(target.clone()).expect("Can't create target view"),
+    );
+
+    let vertices = [
+        Vertex2DBuilder::new([-0.5, -0.5]).color([4.0, 0.2, 0.2]),
+        Vertex2DBuilder::new([0.5, -0.5]).color([0.2, 4.0, 0.2]),
+        Vertex2DBuilder::new([0.0, 0.5]).color([0.2, 0.2, 4.0]),
+    ]
+    .map(Vertex2D::from);
+    let vertex_buf = Buffer::from_iter(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        vertices,
+    )
+    .unwrap();
+    let readback = Buffer::new_slice::<u8>(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        EXTENT[0] as u64 * EXTENT[1] as u64 * 4,
+    )
+    .unwrap();
+
+    let command_buffer_allocator =
+        StandardCommandBufferAllocator::new(device.clone(), Default::default());
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &command_buffer_allocator,
+        queue_family_index,
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    ctx.record(&mut builder, &vertex_buf, BlendMode::Opaque);
+    builder
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+            target,
+            readback.clone(),
+        ))
+        .unwrap();
+    builder
+        .build()
+        .unwrap()
+        .execute(vk_state.queue.clone())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    let pixels = readback.read().unwrap().to_vec();
+    RgbaImage::from_raw(EXTENT[0], EXTENT[1], pixels)
+        .expect("Read back buffer doesn't match the image size")
+        .save("offscreen.png")
+        .expect("Can't save offscreen.png");
+}
@@ -0,0 +1,188 @@
+pub use crate::math::Mat4;
+use crate::math::{Rect, Transform2D};
+
+type Position = [f32; 2];
+
+/// 2D camera looking at the scene from `position`.
+///
+/// `zoom` scales the world (greater than 1.0 magnifies) and `rotation` is
+/// expressed in radians, counter-clockwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera2D {
+    pub position: Position,
+    pub zoom: f32,
+    pub rotation: f32,
+}
+impl Default for Camera2D {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0],
+            zoom: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+impl Camera2D {
+    pub fn new(position: Position) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn pan(&mut self, delta: Position) {
+        self.position[0] += delta[0];
+        self.position[1] += delta[1];
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom *= factor;
+    }
+
+    /// Blend towards `other`, `t` going from 0.0 (`self`) to 1.0 (`other`).
+    pub fn lerp(&self, other: &Camera2D, t: f32) -> Self {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        Self {
+            position: [
+                lerp(self.position[0], other.position[0]),
+                lerp(self.position[1], other.position[1]),
+            ],
+            zoom: lerp(self.zoom, other.zoom),
+            rotation: lerp(self.rotation, other.rotation),
+        }
+    }
+
+    /// Convert a position in window pixels (origin at the top left corner)
+    /// to world coordinates.
+    pub fn screen_to_world(
+        &self,
+        projection: &Projection2D,
+        extent: [f32; 2],
+        screen: Position,
+    ) -> Position {
+        let [width, height] = projection.world_extent(extent);
+        let x = (screen[0] / extent[0] - 0.5) * width;
+        let y = (0.5 - screen[1] / extent[1]) * height;
+        self.transform().apply_point([x, y])
+    }
+
+    /// Transform from the camera space to the world, the inverse of the view.
+    pub fn transform(&self) -> Transform2D {
+        Transform2D::from_scale([1.0 / self.zoom; 2])
+            .then(&Transform2D::from_rotation(self.rotation))
+            .then(&Transform2D::from_translation(self.position))
+    }
+
+    /// Transform from the world to the camera space.
+    pub fn view(&self) -> Transform2D {
+        let [px, py] = self.position;
+        Transform2D::from_translation([-px, -py])
+            .then(&Transform2D::from_rotation(-self.rotation))
+            .then(&Transform2D::from_scale([self.zoom; 2]))
+    }
+
+    /// World area visible in a target of `extent` pixels, enlarged to stay
+    /// axis aligned when the camera is rotated.
+    pub fn visible_rect(&self, projection: &Projection2D, extent: [f32; 2]) -> Rect {
+        let screen = Rect::new([0.0, 0.0], extent);
+        Rect::bounding(
+            screen
+                .corners()
+                .map(|corner| self.screen_to_world(projection, extent, corner)),
+        )
+        .unwrap()
+    }
+
+    /// Matrix moving world coordinates into the camera space.
+    pub fn view_matrix(&self) -> Mat4 {
+        self.view().to_mat4()
+    }
+}
+
+/// Maps world units to normalized device coordinates.
+///
+/// `height` world units are always visible vertically, the horizontal span
+/// follows the aspect ratio of the target. The world Y axis points up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projection2D {
+    pub height: f32,
+}
+impl Default for Projection2D {
+    fn default() -> Self {
+        Self { height: 2.0 }
+    }
+}
+impl Projection2D {
+    pub fn new(height: f32) -> Self {
+        Self { height }
+    }
+
+    /// Visible world span for a target of `extent` pixels.
+    pub fn world_extent(&self, extent: [f32; 2]) -> [f32; 2] {
+        let aspect = extent[0] / extent[1];
+        [self.height * aspect, self.height]
+    }
+
+    pub fn matrix(&self, extent: [f32; 2]) -> Mat4 {
+        let [width, height] = self.world_extent(extent);
+
+        [
+            [2.0 / width, 0.0, 0.0, 0.0],
+            [0.0, -2.0 / height, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Position, b: Position) {
+        assert!(
+            (a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    fn camera() -> Camera2D {
+        Camera2D {
+            position: [1.5, -2.0],
+            zoom: 2.5,
+            rotation: 0.7,
+        }
+    }
+
+    #[test]
+    fn view_inverts_transform() {
+        let camera = camera();
+        let round_trip = camera.view().then(&camera.transform());
+        for point in [[0.0, 0.0], [1.0, 2.0], [-3.0, 0.25]] {
+            assert_close(round_trip.apply_point(point), point);
+        }
+    }
+
+    #[test]
+    fn screen_to_world_matches_view() {
+        let camera = camera();
+        let projection = Projection2D::default();
+        let extent = [800.0, 600.0];
+        assert_close(
+            camera.screen_to_world(&projection, extent, [400.0, 300.0]),
+            camera.position,
+        );
+
+        // Project the world position back through the view, as a fraction of
+        // the target size.
+        let [width, height] = projection.world_extent(extent);
+        for screen in [[0.0, 0.0], [800.0, 600.0], [123.0, 456.0]] {
+            let world = camera.screen_to_world(&projection, extent, screen);
+            let [x, y] = camera.view().apply_point(world);
+            assert_close(
+                [x / width + 0.5, 0.5 - y / height],
+                [screen[0] / extent[0], screen[1] / extent[1]],
+            );
+        }
+    }
+}
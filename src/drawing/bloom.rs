@@ -0,0 +1,342 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    image::{
+        sampler::{Filter, Sampler},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    pipeline::{graphics::color_blend::AttachmentBlend, GraphicsPipeline, Pipeline},
+    render_pass::{Framebuffer, RenderPass},
+};
+
+use crate::drawing::{
+    bloom_down_fs, bloom_up_fs,
+    context::HDR_FORMAT,
+    post::{self, get_framebuffer, PostPass, PostResources},
+    vulkan_helper,
+};
+
+/// Number of downsampled levels, each one half the size of the previous.
+const MAX_LEVELS: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bloom {
+    /// Brightness above which the scene starts glowing.
+    pub threshold: f32,
+    /// Scale of the glow added back to the scene.
+    pub strength: f32,
+}
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            strength: 0.1,
+        }
+    }
+}
+impl Bloom {
+    fn params(&self) -> bloom_down_fs::Params {
+        bloom_down_fs::Params {
+            threshold: self.threshold,
+            strength: self.strength,
+        }
+    }
+}
+
+/// One step of the blur chain.
+struct Level {
+    extent: [u32; 2],
+    down_framebuffer: Arc<Framebuffer>,
+    up_framebuffer: Arc<Framebuffer>,
+    /// Read this level while downsampling into the next one.
+    down_set: Arc<PersistentDescriptorSet>,
+    /// Read this level while upsampling into the previous one.
+    up_set: Arc<PersistentDescriptorSet>,
+}
+
+/// Resources depending on the size of the scene target.
+struct Targets {
+    source_extent: [u32; 2],
+    source_set: Arc<PersistentDescriptorSet>,
+    composite_framebuffer: Arc<Framebuffer>,
+    levels: Vec<Level>,
+}
+
+/// Resources which don't depend on the size of the scene target.
+struct Pipelines {
+    down_pass: Arc<RenderPass>,
+    up_pass: Arc<RenderPass>,
+    down_pipeline: Arc<GraphicsPipeline>,
+    up_pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    params_buf: Subbuffer<bloom_down_fs::Params>,
+}
+
+/// Bright-pass, blur chain and additive composite over the HDR scene.
+///
+/// Draws over its input, so it doesn't need a target of its own.
+pub struct BloomPass {
+    pipelines: Pipelines,
+    targets: Option<Targets>,
+}
+impl BloomPass {
+    pub fn new(
+        vk_state: &vulkan_helper::VulkanState,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+    ) -> Self {
+        let down_pass = vulkano::single_pass_renderpass!(
+            vk_state.device.clone(),
+            attachments: {
+                color: {
+                    format: HDR_FORMAT,
+                    samples: 1,
+                    load_op: DontCare,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )
+        .unwrap();
+        let up_pass = vulkano::single_pass_renderpass!(
+            vk_state.device.clone(),
+            attachments: {
+                color: {
+                    format: HDR_FORMAT,
+                    samples: 1,
+                    load_op: Load,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )
+        .unwrap();
+
+        let down_fs = bloom_down_fs::load(vk_state.device.clone())
+            .expect("Can't compile bloom downsample shader")
+            .entry_point("main")
+            .unwrap();
+        let up_fs = bloom_up_fs::load(vk_state.device.clone())
+            .expect("Can't compile bloom upsample shader")
+            .entry_point("main")
+            .unwrap();
+        let down_pipeline =
+            post::get_fullscreen_pipeline(vk_state, down_pass.clone(), down_fs, None);
+        let up_pipeline = post::get_fullscreen_pipeline(
+            vk_state,
+            up_pass.clone(),
+            up_fs,
+            Some(AttachmentBlend::additive()),
+        );
+
+        let sampler = post::get_sampler(vk_state, Filter::Linear);
+        let params_buf = Buffer::from_data(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            Bloom::default().params(),
+        )
+        .unwrap();
+
+        Self {
+            pipelines: Pipelines {
+                down_pass,
+                up_pass,
+                down_pipeline,
+                up_pipeline,
+                sampler,
+                params_buf,
+            },
+            targets: None,
+        }
+    }
+
+    /// Upload the bloom parameters, used by the next rendered frame.
+    pub fn set_bloom(&self, bloom: &Bloom) {
+        *self
+            .pipelines
+            .params_buf
+            .write()
+            .expect("Bloom buffer is still in use by the GPU") = bloom.params();
+    }
+}
+impl PostPass for BloomPass {
+    fn name(&self) -> &'static str {
+        "bloom"
+    }
+
+    fn in_place(&self) -> bool {
+        true
+    }
+
+    fn set_targets(
+        &mut self,
+        resources: &PostResources,
+        input: Arc<ImageView>,
+        _output: Arc<ImageView>,
+    ) {
+        self.targets = Some(self.pipelines.get_targets(
+            resources.memory_allocator,
+            resources.set_allocator,
+            input,
+        ));
+    }
+
+    fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        let Pipelines {
+            down_pipeline,
+            up_pipeline,
+            ..
+        } = &self.pipelines;
+        let targets = self
+            .targets
+            .as_ref()
+            .expect("Bloom pass recorded before its targets were set");
+        let Some(first) = targets.levels.first() else {
+            return;
+        };
+        let texel_size = |extent: [u32; 2]| extent.map(|x| 1.0 / x as f32);
+
+        draw(
+            builder,
+            down_pipeline,
+            &first.down_framebuffer,
+            &targets.source_set,
+            bloom_down_fs::Pass {
+                texel_size: texel_size(targets.source_extent),
+                prefilter: 1,
+            },
+        );
+        for pair in targets.levels.windows(2) {
+            draw(
+                builder,
+                down_pipeline,
+                &pair[1].down_framebuffer,
+                &pair[0].down_set,
+                bloom_down_fs::Pass {
+                    texel_size: texel_size(pair[0].extent),
+                    prefilter: 0,
+                },
+            );
+        }
+        for pair in targets.levels.windows(2).rev() {
+            draw(
+                builder,
+                up_pipeline,
+                &pair[0].up_framebuffer,
+                &pair[1].up_set,
+                bloom_up_fs::Pass {
+                    texel_size: texel_size(pair[1].extent),
+                    composite: 0,
+                },
+            );
+        }
+        draw(
+            builder,
+            up_pipeline,
+            &targets.composite_framebuffer,
+            &first.up_set,
+            bloom_up_fs::Pass {
+                texel_size: texel_size(first.extent),
+                composite: 1,
+            },
+        );
+    }
+}
+impl Pipelines {
+    fn get_set(
+        &self,
+        allocator: &StandardDescriptorSetAllocator,
+        pipeline: &GraphicsPipeline,
+        source: Arc<ImageView>,
+    ) -> Arc<PersistentDescriptorSet> {
+        let layout = pipeline.layout().set_layouts()[0].clone();
+        PersistentDescriptorSet::new(
+            allocator,
+            layout,
+            [
+                WriteDescriptorSet::image_view_sampler(0, source, self.sampler.clone()),
+                WriteDescriptorSet::buffer(1, self.params_buf.clone()),
+            ],
+            [],
+        )
+        .unwrap()
+    }
+
+    fn get_targets(
+        &self,
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        set_allocator: &StandardDescriptorSetAllocator,
+        source: Arc<ImageView>,
+    ) -> Targets {
+        let [width, height, _] = source.image().extent();
+        let source_extent = [width, height];
+
+        let mut extent = source_extent;
+        let mut levels = Vec::with_capacity(MAX_LEVELS);
+        while levels.len() < MAX_LEVELS && extent != [1, 1] {
+            extent = extent.map(|x| (x / 2).max(1));
+            let image = Image::new(
+                memory_allocator.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format: HDR_FORMAT,
+                    extent: [extent[0], extent[1], 1],
+                    usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+            .unwrap();
+            let view = ImageView::new_default(image).unwrap();
+
+            levels.push(Level {
+                extent,
+                down_framebuffer: get_framebuffer(&self.down_pass, &view),
+                up_framebuffer: get_framebuffer(&self.up_pass, &view),
+                down_set: self.get_set(set_allocator, &self.down_pipeline, view.clone()),
+                up_set: self.get_set(set_allocator, &self.up_pipeline, view),
+            });
+        }
+
+        Targets {
+            source_extent,
+            source_set: self.get_set(set_allocator, &self.down_pipeline, source.clone()),
+            composite_framebuffer: get_framebuffer(&self.up_pass, &source),
+            levels,
+        }
+    }
+}
+
+/// Record a fullscreen draw covering the whole `framebuffer`.
+fn draw<L>(
+    builder: &mut AutoCommandBufferBuilder<L>,
+    pipeline: &Arc<GraphicsPipeline>,
+    framebuffer: &Arc<Framebuffer>,
+    set: &Arc<PersistentDescriptorSet>,
+    push_constants: impl BufferContents,
+) {
+    builder
+        .push_constants(pipeline.layout().clone(), 0, push_constants)
+        .unwrap();
+    post::draw_fullscreen(builder, pipeline, framebuffer, set);
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use image::RgbaImage;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo,
+        PrimaryAutoCommandBuffer,
+    },
+    format::Format,
+    image::ImageUsage,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+};
+
+use crate::drawing::{context::DrawingContext, vulkan_helper};
+
+/// Swapchain formats `FrameCapture::to_image` can convert.
+const SUPPORTED_FORMATS: [Format; 4] = [
+    Format::B8G8R8A8_UNORM,
+    Format::B8G8R8A8_SRGB,
+    Format::R8G8B8A8_UNORM,
+    Format::R8G8B8A8_SRGB,
+];
+
+/// Whether the swapchain images can be copied back to host memory and
+/// converted to 8-bit RGBA.
+pub fn is_supported(ctx: &DrawingContext) -> bool {
+    ctx.swapchain
+        .image_usage()
+        .contains(ImageUsage::TRANSFER_SRC)
+        && SUPPORTED_FORMATS.contains(&ctx.swapchain.image_format())
+}
+
+/// Copy of a swapchain image into host visible memory.
+///
+/// `command_buffer` has to be executed after the frame has been drawn and
+/// before it is presented.
+pub struct FrameCapture {
+    pub command_buffer: Arc<PrimaryAutoCommandBuffer>,
+    buffer: Subbuffer<[u8]>,
+    extent: [u32; 2],
+    format: Format,
+}
+impl FrameCapture {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn new(
+        vk_state: &vulkan_helper::VulkanState,
+        ctx: &DrawingContext,
+        image_idx: u32,
+    ) -> Self {
+        let image = ctx.framebuffers[image_idx as usize].attachments()[0]
+            .image()
+            .clone();
+        let [width, height, _] = image.extent();
+        let buffer = Buffer::new_slice::<u8>(
+            ctx.buffer_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            width as u64 * height as u64 * 4,
+        )
+        .unwrap();
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &ctx.command_buffer_allocator,
+            vk_state.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                image.clone(),
+                buffer.clone(),
+            ))
+            .unwrap();
+
+        Self {
+            command_buffer: builder.build().unwrap(),
+            buffer,
+            extent: [width, height],
+            format: image.format(),
+        }
+    }
+
+    /// Read back the captured pixels, once `command_buffer` execution is
+    /// complete. The swapchain format has to be accepted by `is_supported`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn to_image(&self) -> RgbaImage {
+        let mut pixels = self.buffer.read().unwrap().to_vec();
+        match self.format {
+            Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => {
+                pixels.chunks_exact_mut(4).for_each(|px| px.swap(0, 2))
+            }
+            Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => {}
+            format => unreachable!("Swapchain format {format:?} isn't supported by captures"),
+        }
+
+        RgbaImage::from_raw(self.extent[0], self.extent[1], pixels)
+            .expect("Captured buffer doesn't match the image size")
+    }
+}
@@ -1,14 +1,31 @@
 use std::sync::Arc;
 
+use image::RgbaImage;
+use thiserror::Error;
 use vulkano::{
-    command_buffer::allocator::{
-        StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferUsage, Subbuffer,
     },
-    image::{view::ImageView, Image, ImageUsage},
-    memory::allocator::StandardMemoryAllocator,
+    command_buffer::{
+        allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo},
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer,
+    },
+    descriptor_set::{
+        allocator::{StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo},
+        PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::DeviceOwned,
+    format::Format,
+    image::{
+        sampler::Filter, view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage,
+        SampleCount,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     pipeline::{
         graphics::{
-            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{DepthState, DepthStencilState},
             input_assembly::InputAssemblyState,
             multisample::MultisampleState,
             rasterization::RasterizationState,
@@ -17,54 +34,120 @@ use vulkano::{
             GraphicsPipelineCreateInfo,
         },
         layout::PipelineDescriptorSetLayoutCreateInfo,
-        GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+        GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
     },
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     shader::ShaderModule,
-    swapchain::{PresentMode, Swapchain, SwapchainCreateInfo},
+    swapchain::{
+        self, ColorSpace, PresentFuture, PresentMode, Surface, SurfaceCapabilities, Swapchain,
+        SwapchainCreateInfo, SwapchainPresentInfo,
+    },
+    sync::{self, future::FenceSignalFuture, GpuFuture},
+    VulkanError,
 };
-use winit::window::Window;
 
 use crate::{
-    drawing::{fs, vs, vulkan_helper},
+    camera::Projection2D,
+    drawing::{
+        capture::{self, FrameCapture},
+        post::{PostChain, PostResources, PresentPass},
+        profiler::GpuTimer,
+        scene::SceneRenderer,
+        vs, vulkan_helper,
+    },
     geometry::Vertex2D,
 };
 
+/// Preferred presentation behavior, falling back to a close one when the
+/// surface doesn't support it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PresentModePreference {
+    /// Uncapped, may tear.
+    Immediate,
+    /// Uncapped without tearing, frames which aren't presented in time are dropped.
+    #[default]
+    Mailbox,
+    /// Vsync, always supported.
+    Fifo,
+    /// Vsync, except for late frames which are presented immediately.
+    FifoRelaxed,
+}
+impl PresentModePreference {
+    fn candidates(self) -> &'static [PresentMode] {
+        match self {
+            Self::Immediate => &[PresentMode::Immediate, PresentMode::Mailbox],
+            Self::Mailbox => &[PresentMode::Mailbox],
+            Self::Fifo => &[],
+            Self::FifoRelaxed => &[PresentMode::FifoRelaxed],
+        }
+    }
+
+    /// First supported mode of the fallback chain, ending with `Fifo`.
+    fn resolve(self, supported: &[PresentMode]) -> PresentMode {
+        self.candidates()
+            .iter()
+            .copied()
+            .find(|mode| supported.contains(mode))
+            .unwrap_or(PresentMode::Fifo)
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Immediate => Self::Mailbox,
+            Self::Mailbox => Self::Fifo,
+            Self::Fifo => Self::FifoRelaxed,
+            Self::FifoRelaxed => Self::Immediate,
+        }
+    }
+}
+
+fn get_present_mode(
+    vk_ctx: &vulkan_helper::VulkanState,
+    surface: &Surface,
+    preference: PresentModePreference,
+) -> PresentMode {
+    let supported = vk_ctx
+        .physical_device
+        .surface_present_modes(surface, Default::default())
+        .map(|modes| modes.collect::<Vec<_>>())
+        .unwrap_or_default();
+    preference.resolve(&supported)
+}
+
 fn get_swapchain(
-    window: &Window,
+    window_size: [u32; 2],
     vk_ctx: &vulkan_helper::VulkanState,
+    surface: Arc<Surface>,
+    present_mode: PresentModePreference,
 ) -> (Arc<Swapchain>, Box<[Arc<Image>]>) {
     let caps = vk_ctx
         .physical_device
-        .surface_capabilities(&vk_ctx.surface, Default::default())
+        .surface_capabilities(&surface, Default::default())
         .expect("Failed to get surface capabilities");
-    let dimensions = window.inner_size();
     let composite_alpha = caps.supported_composite_alpha.into_iter().next().unwrap();
-    let image_format = vk_ctx
+    let formats = vk_ctx
         .physical_device
-        .surface_formats(&vk_ctx.surface, Default::default())
-        .unwrap()[0]
-        .0;
-    let image_count = u32::min(
-        caps.min_image_count + 1,
-        caps.max_image_count.unwrap_or(u32::MAX),
-    );
-    let present_mode = if caps
-        .compatible_present_modes
-        .contains(&PresentMode::Mailbox)
-    {
-        PresentMode::Mailbox
-    } else {
-        PresentMode::Fifo
-    };
+        .surface_formats(&surface, Default::default())
+        .unwrap();
+    let (image_format, _) = formats
+        .iter()
+        .copied()
+        .find(|(format, color_space)| is_srgb(*format) && *color_space == ColorSpace::SrgbNonLinear)
+        .unwrap_or(formats[0]);
+    let image_count = get_image_count(&caps, None);
+    let present_mode = get_present_mode(vk_ctx, &surface, present_mode);
+    // Transfers are only used to capture frames, which isn't possible on
+    // every platform.
+    let image_usage =
+        ImageUsage::COLOR_ATTACHMENT | (caps.supported_usage_flags & ImageUsage::TRANSFER_SRC);
     let (swapchain, images) = Swapchain::new(
         vk_ctx.device.clone(),
-        vk_ctx.surface.clone(),
+        surface,
         SwapchainCreateInfo {
             min_image_count: image_count,
             image_format,
-            image_extent: dimensions.into(),
-            image_usage: ImageUsage::COLOR_ATTACHMENT,
+            image_extent: get_image_extent(window_size, &caps),
+            image_usage,
             composite_alpha,
             present_mode,
             ..Default::default()
@@ -75,6 +158,56 @@ fn get_swapchain(
     (swapchain, images.into_boxed_slice())
 }
 
+/// Requested number of swapchain images clamped to the surface limits, one
+/// more than the minimum when unset.
+fn get_image_count(caps: &SurfaceCapabilities, requested: Option<u32>) -> u32 {
+    requested.unwrap_or(caps.min_image_count + 1).clamp(
+        caps.min_image_count,
+        caps.max_image_count.unwrap_or(u32::MAX),
+    )
+}
+
+/// Window inner size, in pixels, clamped to the extents supported by the surface.
+fn get_image_extent(window_size: [u32; 2], caps: &SurfaceCapabilities) -> [u32; 2] {
+    [0, 1].map(|dim| {
+        window_size[dim]
+            .max(caps.min_image_extent[dim])
+            .min(caps.max_image_extent[dim])
+    })
+}
+
+/// Whether writes to `format` are encoded with the sRGB transfer function.
+pub fn is_srgb(format: Format) -> bool {
+    matches!(
+        format,
+        Format::B8G8R8A8_SRGB | Format::R8G8B8A8_SRGB | Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+/// Pass writing the post chain output into a target of `format`.
+pub(crate) fn get_present_pass(
+    vk_state: &vulkan_helper::VulkanState,
+    format: Format,
+) -> Arc<RenderPass> {
+    vulkano::single_pass_renderpass!(
+        vk_state.device.clone(),
+        attachments: {
+            color: {
+                format: format,
+                samples: 1,
+                load_op: DontCare,
+                store_op: Store,
+            },
+
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {},
+        },
+    )
+    .unwrap()
+}
+
 fn get_framebuffers(
     images: &[Arc<Image>],
     render_pass: &Arc<RenderPass>,
@@ -95,13 +228,315 @@ fn get_framebuffers(
         .collect::<Box<_>>()
 }
 
-fn get_pipeline(
+/// Format of the offscreen target the scene radiance is drawn into.
+pub const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+/// Format of the scene depth attachment, supported by every device.
+pub const DEPTH_FORMAT: Format = Format::D16_UNORM;
+
+/// Depth of the scene draws in `layer`, to push as `vs::Layer`. With the
+/// depth test, higher layers hide lower ones whatever the draw order.
+pub fn layer_depth(layer: u16) -> f32 {
+    1.0 - (layer as f32 + 1.0) / (u16::MAX as f32 + 2.0)
+}
+
+pub(crate) fn get_hdr_target(
+    allocator: &Arc<StandardMemoryAllocator>,
+    extent: [u32; 2],
+) -> Arc<ImageView> {
+    let image = Image::new(
+        allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: HDR_FORMAT,
+            extent: [extent[0], extent[1], 1],
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    ImageView::new_default(image).unwrap()
+}
+
+/// Size of the buffers `DrawingContext::transient_allocator` suballocates from.
+const TRANSIENT_ARENA_SIZE: u64 = 64 * 1024;
+
+/// Host writable buffers handed out for a single frame or upload.
+///
+/// Arenas are recycled once every subbuffer allocated from them has been
+/// dropped, which happens when the command buffers using them have completed,
+/// so steady state uploads don't allocate device memory.
+pub(crate) fn get_transient_allocator(
+    allocator: &Arc<StandardMemoryAllocator>,
+) -> SubbufferAllocator {
+    SubbufferAllocator::new(
+        allocator.clone(),
+        SubbufferAllocatorCreateInfo {
+            arena_size: TRANSIENT_ARENA_SIZE,
+            buffer_usage: BufferUsage::TRANSFER_SRC
+                | BufferUsage::VERTEX_BUFFER
+                | BufferUsage::UNIFORM_BUFFER,
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+    )
+}
+
+/// Largest sample count supported by the device for color attachments, up
+/// to `requested`.
+pub fn get_sample_count(vk_state: &vulkan_helper::VulkanState, requested: u32) -> SampleCount {
+    let supported = vk_state
+        .physical_device
+        .properties()
+        .framebuffer_color_sample_counts;
+    [
+        SampleCount::Sample64,
+        SampleCount::Sample32,
+        SampleCount::Sample16,
+        SampleCount::Sample8,
+        SampleCount::Sample4,
+        SampleCount::Sample2,
+    ]
+    .into_iter()
+    .find(|&count| count as u32 <= requested && supported.contains_enum(count))
+    .unwrap_or(SampleCount::Sample1)
+}
+
+/// Pass drawing the scene into the HDR target, through a multisampled
+/// attachment resolved at the end of the pass when `samples` is above one.
+/// With `depth`, draws are also tested against a depth attachment of the
+/// same sample count.
+pub(crate) fn get_scene_pass(
+    vk_state: &vulkan_helper::VulkanState,
+    samples: SampleCount,
+    depth: bool,
+) -> Arc<RenderPass> {
+    let device = vk_state.device.clone();
+    let render_pass = match (samples == SampleCount::Sample1, depth) {
+        (true, false) => vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                color: {
+                    format: HDR_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        ),
+        (true, true) => vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                color: {
+                    format: HDR_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+                depth: {
+                    format: DEPTH_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {depth},
+            },
+        ),
+        (false, false) => vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                multisampled: {
+                    format: HDR_FORMAT,
+                    samples: samples as u32,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+                color: {
+                    format: HDR_FORMAT,
+                    samples: 1,
+                    load_op: DontCare,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [multisampled],
+                color_resolve: [color],
+                depth_stencil: {},
+            },
+        ),
+        (false, true) => vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                multisampled: {
+                    format: HDR_FORMAT,
+                    samples: samples as u32,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+                color: {
+                    format: HDR_FORMAT,
+                    samples: 1,
+                    load_op: DontCare,
+                    store_op: Store,
+                },
+                depth: {
+                    format: DEPTH_FORMAT,
+                    samples: samples as u32,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+            },
+            pass: {
+                color: [multisampled],
+                color_resolve: [color],
+                depth_stencil: {depth},
+            },
+        ),
+    };
+    render_pass.unwrap()
+}
+
+pub(crate) fn get_msaa_target(
+    allocator: &Arc<StandardMemoryAllocator>,
+    extent: [u32; 2],
+    samples: SampleCount,
+) -> Option<Arc<ImageView>> {
+    if samples == SampleCount::Sample1 {
+        return None;
+    }
+
+    let image = Image::new(
+        allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: HDR_FORMAT,
+            extent: [extent[0], extent[1], 1],
+            samples,
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    Some(ImageView::new_default(image).unwrap())
+}
+
+pub(crate) fn get_depth_target(
+    allocator: &Arc<StandardMemoryAllocator>,
+    extent: [u32; 2],
+    samples: SampleCount,
+) -> Arc<ImageView> {
+    let image = Image::new(
+        allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: DEPTH_FORMAT,
+            extent: [extent[0], extent[1], 1],
+            samples,
+            usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    ImageView::new_default(image).unwrap()
+}
+
+pub(crate) fn get_scene_framebuffer(
+    hdr_target: &Arc<ImageView>,
+    msaa_target: Option<&Arc<ImageView>>,
+    depth_target: Option<&Arc<ImageView>>,
+    scene_pass: &Arc<RenderPass>,
+) -> Arc<Framebuffer> {
+    Framebuffer::new(
+        scene_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: msaa_target
+                .into_iter()
+                .chain([hdr_target])
+                .chain(depth_target)
+                .cloned()
+                .collect(),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+pub(crate) fn get_viewport(target: &Arc<ImageView>) -> Viewport {
+    let [width, height, _] = target.image().extent();
+    Viewport {
+        offset: [0.0, 0.0],
+        extent: [width as f32, height as f32],
+        depth_range: 0.0..=1.0,
+    }
+}
+
+/// How scene draws are combined with what has already been drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Overwrite, e.g. walls.
+    #[default]
+    Opaque,
+    /// Blend by the source alpha, e.g. translucent overlays.
+    Alpha,
+    /// Add to the destination, e.g. light gizmos.
+    Additive,
+}
+impl BlendMode {
+    fn attachment_blend(self) -> Option<AttachmentBlend> {
+        match self {
+            Self::Opaque => None,
+            Self::Alpha => Some(AttachmentBlend::alpha()),
+            Self::Additive => Some(AttachmentBlend::additive()),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Opaque => Self::Alpha,
+            Self::Alpha => Self::Additive,
+            Self::Additive => Self::Opaque,
+        }
+    }
+}
+
+/// Scene pipelines, one per blend mode, sharing the same layout.
+#[derive(Clone)]
+pub struct ScenePipelines {
+    pub opaque: Arc<GraphicsPipeline>,
+    pub alpha: Arc<GraphicsPipeline>,
+    pub additive: Arc<GraphicsPipeline>,
+}
+impl ScenePipelines {
+    pub fn get(&self, mode: BlendMode) -> &Arc<GraphicsPipeline> {
+        match mode {
+            BlendMode::Opaque => &self.opaque,
+            BlendMode::Alpha => &self.alpha,
+            BlendMode::Additive => &self.additive,
+        }
+    }
+
+    pub fn layout(&self) -> &Arc<PipelineLayout> {
+        self.opaque.layout()
+    }
+}
+
+pub(crate) fn get_scene_pipelines(
     viewport: Viewport,
     vk_ctx: &vulkan_helper::VulkanState,
     render_pass: Arc<RenderPass>,
     vs: Arc<ShaderModule>,
     fs: Arc<ShaderModule>,
-) -> Arc<GraphicsPipeline> {
+) -> ScenePipelines {
     let vs = vs.entry_point("main").unwrap();
     let fs = fs.entry_point("main").unwrap();
 
@@ -122,42 +557,132 @@ fn get_pipeline(
 
     let subpass = Subpass::from(render_pass, 0).unwrap();
 
-    GraphicsPipeline::new(
-        vk_ctx.device.clone(),
-        None,
-        GraphicsPipelineCreateInfo {
-            stages: stages.into_iter().collect(),
-            vertex_input_state: Some(vertex_input_state),
-            input_assembly_state: Some(InputAssemblyState::default()),
-            viewport_state: Some(ViewportState {
-                viewports: [viewport].into_iter().collect(),
-                ..Default::default()
-            }),
-            rasterization_state: Some(RasterizationState::default()),
-            multisample_state: Some(MultisampleState::default()),
-            color_blend_state: Some(ColorBlendState::with_attachment_states(
-                subpass.num_color_attachments(),
-                ColorBlendAttachmentState::default(),
-            )),
-            subpass: Some(subpass.into()),
-            ..GraphicsPipelineCreateInfo::layout(layout)
-        },
+    let get_pipeline = |mode: BlendMode| {
+        GraphicsPipeline::new(
+            vk_ctx.device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.iter().cloned().collect(),
+                vertex_input_state: Some(vertex_input_state.clone()),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [viewport.clone()].into_iter().collect(),
+                    ..Default::default()
+                }),
+                rasterization_state: Some(RasterizationState::default()),
+                depth_stencil_state: subpass
+                    .subpass_desc()
+                    .depth_stencil_attachment
+                    .is_some()
+                    .then(|| DepthStencilState {
+                        depth: Some(DepthState::simple()),
+                        ..Default::default()
+                    }),
+                multisample_state: Some(MultisampleState {
+                    rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                    ..Default::default()
+                }),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState {
+                        blend: mode.attachment_blend(),
+                        ..Default::default()
+                    },
+                )),
+                subpass: Some(subpass.clone().into()),
+                ..GraphicsPipelineCreateInfo::layout(layout.clone())
+            },
+        )
+        .unwrap()
+    };
+
+    ScenePipelines {
+        opaque: get_pipeline(BlendMode::Opaque),
+        alpha: get_pipeline(BlendMode::Alpha),
+        additive: get_pipeline(BlendMode::Additive),
+    }
+}
+
+pub(crate) fn get_camera_set(
+    allocator: &StandardDescriptorSetAllocator,
+    pipeline: &GraphicsPipeline,
+    camera_buf: &Subbuffer<vs::Camera>,
+) -> Arc<PersistentDescriptorSet> {
+    let layout = pipeline.layout().set_layouts()[0].clone();
+    PersistentDescriptorSet::new(
+        allocator,
+        layout,
+        [WriteDescriptorSet::buffer(0, camera_buf.clone())],
+        [],
     )
     .unwrap()
 }
 
+/// Size of the targets the scene and the post chain are drawn into, before
+/// being scaled to the swapchain size.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RenderResolution {
+    /// Same size as the swapchain.
+    #[default]
+    Native,
+    /// Swapchain size multiplied by a factor, e.g. 0.5 for half resolution.
+    Scaled(f32),
+    /// Fixed size, whatever the window size, e.g. for pixel art.
+    Fixed([u32; 2]),
+}
+impl RenderResolution {
+    pub fn extent(&self, swapchain_extent: [u32; 2]) -> [u32; 2] {
+        let extent = match *self {
+            Self::Native => swapchain_extent,
+            Self::Scaled(factor) => swapchain_extent.map(|x| (x as f32 * factor).round() as u32),
+            Self::Fixed(extent) => extent,
+        };
+        extent.map(|x| x.max(1))
+    }
+}
+
+/// Maximum number of passes timed by `DrawingContext::gpu_timer`, the
+/// remaining ones aren't measured.
+const MAX_TIMED_PASSES: u32 = 16;
+
+/// Color of the scene where nothing is drawn, see `SceneRenderer::set_clear_color`.
+pub const DEFAULT_CLEAR_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 0.1];
+
 pub struct DrawingContext {
     pub command_buffer_allocator: StandardCommandBufferAllocator,
+    pub descriptor_set_allocator: StandardDescriptorSetAllocator,
     pub buffer_allocator: Arc<StandardMemoryAllocator>,
+    /// Short lived buffers, e.g. staging data or per frame uniforms, see
+    /// `get_transient_allocator`.
+    pub transient_allocator: SubbufferAllocator,
     pub swapchain: Arc<Swapchain>,
+    /// Requested present mode, `swapchain` holds the one actually used.
+    pub present_mode: PresentModePreference,
+    /// Requested number of swapchain images, see `set_image_count`.
+    pub image_count: Option<u32>,
+    /// Swapchain framebuffers, targeted by the present pass.
     pub framebuffers: Box<[Arc<Framebuffer>]>,
     pub render_pass: Arc<RenderPass>,
-    pub pipeline: Arc<GraphicsPipeline>,
-    pub vs: Arc<ShaderModule>,
-    pub fs: Arc<ShaderModule>,
+    /// Scene and post chain, drawn at `resolution` then scaled to the
+    /// swapchain size by `present`.
+    pub scene: SceneRenderer,
+    /// Size of the scene targets, see `set_resolution`.
+    pub resolution: RenderResolution,
+    /// Filter used to scale the post chain output to the swapchain size.
+    pub upscale_filter: Filter,
+    pub present: PresentPass,
+    pub gpu_timer: Option<GpuTimer>,
 }
 impl DrawingContext {
-    pub fn new(vk_state: &vulkan_helper::VulkanState, window: &Window) -> Self {
+    /// `surface` is owned by the context, which has to be dropped along with
+    /// the window, e.g. when the application is suspended.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn new(
+        vk_state: &vulkan_helper::VulkanState,
+        surface: Arc<Surface>,
+        window_size: [u32; 2],
+        present_mode: PresentModePreference,
+    ) -> Self {
         let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(
             vk_state.device.clone(),
         ));
@@ -165,89 +690,432 @@ impl DrawingContext {
             vk_state.device.clone(),
             StandardCommandBufferAllocatorCreateInfo::default(),
         );
-        let vs = vs::load(vk_state.device.clone()).expect("Can't compile vertex shader");
-        let fs = fs::load(vk_state.device.clone()).expect("Can't compile fragment shader");
-
-        let (swapchain, images) = get_swapchain(window, vk_state);
-        let render_pass = vulkano::single_pass_renderpass!(
+        let descriptor_set_allocator = StandardDescriptorSetAllocator::new(
             vk_state.device.clone(),
-            attachments: {
-                color: {
-                    format: swapchain.image_format(),
-                    samples: 1,
-                    load_op: Clear,
-                    store_op: Store,
-                },
+            StandardDescriptorSetAllocatorCreateInfo::default(),
+        );
 
-            },
-            pass: {
-                color: [color],
-                depth_stencil: {},
-            },
-        )
-        .unwrap();
+        let (swapchain, images) = get_swapchain(window_size, vk_state, surface, present_mode);
+        let render_pass = get_present_pass(vk_state, swapchain.image_format());
         let framebufs = get_framebuffers(&images, &render_pass);
-        let viewport = Viewport {
-            offset: [0.0, 0.0],
-            extent: window.inner_size().into(),
-            depth_range: 0.0..=1.0,
-        };
-        let pipeline = get_pipeline(
-            viewport,
+        let resolution = RenderResolution::default();
+        let upscale_filter = Filter::Linear;
+        let scene = SceneRenderer::new(
+            vk_state,
+            &PostResources {
+                memory_allocator: &memory_allocator,
+                set_allocator: &descriptor_set_allocator,
+            },
+            resolution.extent(swapchain.image_extent()),
+            window_size,
+        );
+        let gpu_timer = GpuTimer::new(vk_state, images.len() as u32, MAX_TIMED_PASSES);
+        let present = PresentPass::new(
             vk_state,
+            &descriptor_set_allocator,
             render_pass.clone(),
-            vs.clone(),
-            fs.clone(),
+            scene.post_output.clone(),
+            upscale_filter,
+            !is_srgb(swapchain.image_format()),
         );
 
         DrawingContext {
             command_buffer_allocator,
+            descriptor_set_allocator,
+            transient_allocator: get_transient_allocator(&memory_allocator),
             buffer_allocator: memory_allocator,
             swapchain,
-            pipeline,
-            render_pass,
+            present_mode,
+            image_count: None,
             framebuffers: framebufs,
-            vs,
-            fs,
+            render_pass,
+            scene,
+            resolution,
+            upscale_filter,
+            present,
+            gpu_timer,
+        }
+    }
+
+    pub fn revoke_swapchain(self, window_size: [u32; 2]) -> Self {
+        let create_info = self.swapchain.create_info();
+        self.recreate_swapchain(window_size, create_info)
+    }
+
+    /// Switch to the closest supported present mode, the swapchain is
+    /// recreated so command buffers have to be recorded again afterwards.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn set_present_mode(
+        self,
+        vk_state: &vulkan_helper::VulkanState,
+        window_size: [u32; 2],
+        preference: PresentModePreference,
+    ) -> Self {
+        let create_info = SwapchainCreateInfo {
+            present_mode: get_present_mode(vk_state, self.swapchain.surface(), preference),
+            ..self.swapchain.create_info()
+        };
+
+        Self {
+            present_mode: preference,
+            ..self
+        }
+        .recreate_swapchain(window_size, create_info)
+    }
+
+    /// Request `image_count` swapchain images, e.g. 2 for double buffering to
+    /// lower the latency or 3 for triple buffering, clamped to what the
+    /// surface supports. `None` restores the default of one more than the
+    /// surface minimum. Command buffers have to be recorded again afterwards.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn set_image_count(
+        self,
+        vk_state: &vulkan_helper::VulkanState,
+        window_size: [u32; 2],
+        image_count: Option<u32>,
+    ) -> Self {
+        let caps = vk_state
+            .physical_device
+            .surface_capabilities(self.swapchain.surface(), Default::default())
+            .expect("Failed to get surface capabilities");
+        let create_info = SwapchainCreateInfo {
+            min_image_count: get_image_count(&caps, image_count),
+            ..self.swapchain.create_info()
+        };
+
+        let ctx = Self {
+            image_count,
+            ..self
+        }
+        .recreate_swapchain(window_size, create_info);
+        // Timestamps are recorded per swapchain image.
+        let new_gpu_timer =
+            GpuTimer::new(vk_state, ctx.framebuffers.len() as u32, MAX_TIMED_PASSES);
+
+        Self {
+            gpu_timer: new_gpu_timer,
+            ..ctx
         }
     }
 
-    pub fn revoke_swapchain(self, window: &Window) -> Self {
-        let new_dimensions = window.inner_size();
+    /// `window_size` must not be zero, see `window::is_drawable`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn recreate_swapchain(self, window_size: [u32; 2], create_info: SwapchainCreateInfo) -> Self {
+        let caps = self
+            .swapchain
+            .device()
+            .physical_device()
+            .surface_capabilities(self.swapchain.surface(), Default::default())
+            .expect("Failed to get surface capabilities");
         let (new_swapchain, new_images) = self
             .swapchain
             .recreate(SwapchainCreateInfo {
-                image_extent: new_dimensions.into(),
-                ..self.swapchain.create_info()
+                image_extent: get_image_extent(window_size, &caps),
+                ..create_info
             })
-            .expect("Failed to recreate swapchain {e");
+            .expect("Failed to recreate swapchain");
 
         let new_framebuffers = get_framebuffers(&new_images, &self.render_pass);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            extent = ?new_swapchain.image_extent(),
+            present_mode = ?new_swapchain.present_mode(),
+            "Swapchain recreated"
+        );
 
         Self {
             swapchain: new_swapchain,
             framebuffers: new_framebuffers,
             ..self
         }
+        .rebuild_targets()
+    }
+
+    /// Change the size the scene is drawn at, command buffers have to be
+    /// recorded again afterwards.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn set_resolution(
+        self,
+        vk_state: &vulkan_helper::VulkanState,
+        window_size: [u32; 2],
+        resolution: RenderResolution,
+    ) -> Self {
+        Self { resolution, ..self }
+            .rebuild_targets()
+            .resize_viewport(vk_state, window_size)
+    }
+
+    /// See `SceneRenderer::set_msaa`. Command buffers have to be recorded
+    /// again afterwards.
+    pub fn set_msaa(self, vk_state: &vulkan_helper::VulkanState, samples: u32) -> Self {
+        let resources = PostResources {
+            memory_allocator: &self.buffer_allocator,
+            set_allocator: &self.descriptor_set_allocator,
+        };
+        let new_scene = self.scene.set_msaa(vk_state, &resources, samples);
+
+        Self {
+            scene: new_scene,
+            ..self
+        }
+        .rebind_present()
     }
 
-    pub fn resize_viewport(self, vk_state: &vulkan_helper::VulkanState, window: &Window) -> Self {
-        let viewport = Viewport {
-            offset: [0.0, 0.0],
-            extent: window.inner_size().into(),
-            depth_range: 0.0..=1.0,
+    /// See `SceneRenderer::set_depth_test`. Command buffers have to be
+    /// recorded again afterwards.
+    pub fn set_depth_test(self, vk_state: &vulkan_helper::VulkanState, depth_test: bool) -> Self {
+        let resources = PostResources {
+            memory_allocator: &self.buffer_allocator,
+            set_allocator: &self.descriptor_set_allocator,
         };
-        let new_pipeline = get_pipeline(
-            viewport,
-            vk_state,
-            self.render_pass.clone(),
-            self.vs.clone(),
-            self.fs.clone(),
+        let new_scene = self.scene.set_depth_test(vk_state, &resources, depth_test);
+
+        Self {
+            scene: new_scene,
+            ..self
+        }
+        .rebind_present()
+    }
+
+    /// See `SceneRenderer::set_clear_color`. Command buffers have to be
+    /// recorded again afterwards.
+    pub fn set_clear_color(self, clear_color: [f32; 4]) -> Self {
+        Self {
+            scene: self.scene.set_clear_color(clear_color),
+            ..self
+        }
+    }
+
+    /// Command buffers have to be recorded again afterwards.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn set_upscale_filter(self, vk_state: &vulkan_helper::VulkanState, filter: Filter) -> Self {
+        let new_present = self
+            .present
+            .set_filter(vk_state, &self.descriptor_set_allocator, filter);
+
+        Self {
+            present: new_present,
+            upscale_filter: filter,
+            ..self
+        }
+    }
+
+    /// Recreate the scene targets for the current swapchain and resolution,
+    /// the pipelines are rebuilt by `resize_viewport`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn rebuild_targets(self) -> Self {
+        let extent = self.resolution.extent(self.swapchain.image_extent());
+        let resources = PostResources {
+            memory_allocator: &self.buffer_allocator,
+            set_allocator: &self.descriptor_set_allocator,
+        };
+        let new_scene = self.scene.resize_targets(&resources, extent);
+
+        Self {
+            scene: new_scene,
+            ..self
+        }
+        .rebind_present()
+    }
+
+    /// Add, remove or toggle post-processing passes. Command buffers have to
+    /// be recorded again afterwards.
+    pub fn edit_post_chain(self, edit: impl FnOnce(&mut PostChain)) -> Self {
+        let resources = PostResources {
+            memory_allocator: &self.buffer_allocator,
+            set_allocator: &self.descriptor_set_allocator,
+        };
+        let new_scene = self.scene.edit_post_chain(&resources, edit);
+
+        Self {
+            scene: new_scene,
+            ..self
+        }
+        .rebind_present()
+    }
+
+    /// Point the present pass at the current post chain output.
+    fn rebind_present(self) -> Self {
+        let new_present = self.present.rebind_source(
+            &self.descriptor_set_allocator,
+            self.scene.post_output.clone(),
         );
 
         Self {
-            pipeline: new_pipeline,
+            present: new_present,
+            ..self
+        }
+    }
+
+    pub fn resize_viewport(
+        self,
+        vk_state: &vulkan_helper::VulkanState,
+        window_size: [u32; 2],
+    ) -> Self {
+        let resources = PostResources {
+            memory_allocator: &self.buffer_allocator,
+            set_allocator: &self.descriptor_set_allocator,
+        };
+        let new_scene = self.scene.rebuild_pipelines(vk_state, &resources);
+        // The scene is stretched to the window, keep the window aspect ratio.
+        new_scene.upload_projection(window_size);
+
+        Self {
+            scene: new_scene,
             ..self
         }
     }
+
+    /// Names of the passes timed by `gpu_timer`, in submission order.
+    pub fn timed_passes(&self) -> Vec<&'static str> {
+        let mut names = vec!["geometry"];
+        names.extend(self.scene.post_chain.enabled_names());
+        names.push("present");
+        names.truncate(MAX_TIMED_PASSES as usize);
+        names
+    }
+
+    /// Record the scene drawing `vertex_buf`, the post chain and the pass
+    /// presenting its result into the swapchain image `image_idx`, timing
+    /// each of them when `gpu_timer` is available.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_idx: u32,
+        vertex_buf: &Subbuffer<[Vertex2D]>,
+        blend_mode: BlendMode,
+    ) {
+        let end_pass = |builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+                        pass: u32| {
+            if let Some(timer) = &self.gpu_timer {
+                timer.end_pass(builder, image_idx, pass);
+            }
+        };
+
+        if let Some(timer) = &self.gpu_timer {
+            timer.begin(builder, image_idx);
+        }
+        self.scene.record(builder, vertex_buf, blend_mode, end_pass);
+        self.present
+            .record(builder, &self.framebuffers[image_idx as usize]);
+        end_pass(
+            builder,
+            self.scene.post_chain.enabled_names().count() as u32 + 1,
+        );
+    }
+
+    /// Acquire the next swapchain image and submit the matching command
+    /// buffer, recorded by `record`, followed by its presentation. With
+    /// `read_back`, the image is also copied to host memory before being
+    /// presented, see `capture::is_supported`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn submit_frame(
+        &self,
+        vk_state: &vulkan_helper::VulkanState,
+        command_buffers: &[Arc<PrimaryAutoCommandBuffer>],
+        read_back: bool,
+    ) -> Result<PendingFrame, DrawingError> {
+        if read_back && !capture::is_supported(self) {
+            return Err(DrawingError::CaptureUnsupported);
+        }
+
+        let (image_idx, suboptimal, acquire_future) =
+            swapchain::acquire_next_image(self.swapchain.clone(), None)
+                .map_err(|e| get_drawing_error(e.unwrap(), "Can't acquire the next image"))?;
+        if suboptimal {
+            return Err(DrawingError::ObsoleteSwapchain);
+        }
+
+        let capture = read_back.then(|| FrameCapture::new(vk_state, self, image_idx));
+
+        let mut draw_future = sync::now(vk_state.device.clone())
+            .join(acquire_future)
+            .then_execute(
+                vk_state.queue.clone(),
+                command_buffers[image_idx as usize].clone(),
+            )
+            .unwrap()
+            .boxed();
+        if let Some(capture) = &capture {
+            draw_future = draw_future
+                .then_execute(vk_state.queue.clone(), capture.command_buffer.clone())
+                .unwrap()
+                .boxed();
+        }
+        let future = draw_future
+            .then_swapchain_present(
+                vk_state.queue.clone(),
+                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_idx),
+            )
+            .then_signal_fence_and_flush()
+            .map_err(|e| get_drawing_error(e.unwrap(), "Rendering failed"))?;
+
+        Ok(PendingFrame {
+            image_idx,
+            future,
+            capture,
+        })
+    }
+
+    /// Draw and present a frame, returning its pixels read back from the
+    /// swapchain image.
+    pub fn capture_frame(
+        &self,
+        vk_state: &vulkan_helper::VulkanState,
+        command_buffers: &[Arc<PrimaryAutoCommandBuffer>],
+    ) -> Result<RgbaImage, DrawingError> {
+        self.submit_frame(vk_state, command_buffers, true)?
+            .wait()
+            .map(|image| image.expect("Frame was submitted with read back"))
+    }
+
+    /// Change the world to clip space mapping, for the current window size.
+    pub fn set_projection(&mut self, projection: Projection2D) {
+        self.scene
+            .set_projection(projection, self.swapchain.image_extent());
+    }
+}
+
+/// Failures a frame can recover from, the other ones panic.
+#[derive(Debug, Error)]
+pub enum DrawingError {
+    #[error("Swapchain needs to be recreate")]
+    ObsoleteSwapchain,
+    #[error("Swapchain images can't be captured on this platform or in this format")]
+    CaptureUnsupported,
+    #[error("Rendering device has been lost")]
+    DeviceLost,
+    #[error("Window surface has been lost")]
+    SurfaceLost,
+}
+
+/// Map the errors a frame can recover from, panic on the others.
+fn get_drawing_error(e: VulkanError, what: &str) -> DrawingError {
+    match e {
+        VulkanError::OutOfDate => DrawingError::ObsoleteSwapchain,
+        VulkanError::DeviceLost => DrawingError::DeviceLost,
+        VulkanError::SurfaceLost => DrawingError::SurfaceLost,
+        e => panic!("{what}: {e}"),
+    }
+}
+
+/// Frame submitted by `DrawingContext::submit_frame`.
+pub struct PendingFrame {
+    /// Swapchain image the frame is drawn into.
+    pub image_idx: u32,
+    future: FenceSignalFuture<PresentFuture<Box<dyn GpuFuture>>>,
+    capture: Option<FrameCapture>,
+}
+impl PendingFrame {
+    /// Block until the frame is drawn, returning its pixels when it was
+    /// submitted with `read_back`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn wait(self) -> Result<Option<RgbaImage>, DrawingError> {
+        self.future
+            .wait(None)
+            .map_err(|e| get_drawing_error(e.unwrap(), "Rendering failed"))?;
+
+        Ok(self.capture.map(|capture| capture.to_image()))
+    }
 }
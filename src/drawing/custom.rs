@@ -0,0 +1,360 @@
+use std::{error::Error, fs, path::Path, sync::Arc};
+
+use thiserror::Error;
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{layout::DescriptorType, PersistentDescriptorSet, WriteDescriptorSet},
+    format::NumericType,
+    image::{
+        sampler::{Filter, Sampler},
+        view::ImageView,
+    },
+    pipeline::GraphicsPipeline,
+    render_pass::{Framebuffer, RenderPass},
+    shader::{
+        spirv::{bytes_to_words, ExecutionModel},
+        EntryPointInfo, ShaderInterfaceEntryType, ShaderModule, ShaderModuleCreateInfo,
+    },
+};
+
+use crate::drawing::{
+    post::{self, PostPass, PostResources},
+    vs, vulkan_helper,
+};
+
+/// Bindings of the first descriptor set a custom shader may use.
+const SOURCE_BINDING: u32 = 0;
+const CAMERA_BINDING: u32 = 1;
+/// `vec2 uv` written by the fullscreen vertex shader at location 0.
+const UV_INPUT: ShaderInterfaceEntryType = ShaderInterfaceEntryType {
+    base_type: NumericType::Float,
+    num_components: 2,
+    num_elements: 1,
+    is_64bit: false,
+};
+
+#[derive(Debug, Error)]
+pub enum ShaderError {
+    #[error("Can't read the shader file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid SPIR-V: {0}")]
+    Spirv(String),
+    #[error("Shader doesn't match the custom pass interface: {0}")]
+    Interface(String),
+    #[error("Can't create the pipeline: {0}")]
+    Pipeline(Box<dyn Error + Send + Sync>),
+}
+
+/// Fullscreen pass running a fragment shader supplied at runtime, e.g. for
+/// grading or stylization specific to an application.
+///
+/// The SPIR-V module needs a `main` fragment entry point writing its color
+/// to location 0. It may only read `vec2 uv` at location 0, going from 0.0
+/// to 1.0 across the target, and use these resources:
+///
+/// - set 0, binding 0: `sampler2D`, the output of the previous pass in
+///   linear HDR values.
+/// - set 0, binding 1: uniform block `{ mat4 view; mat4 proj; }`, the camera
+///   of the scene.
+///
+/// Push constants aren't supported.
+pub struct CustomPass {
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    camera_buf: Subbuffer<vs::Camera>,
+    uses_camera: bool,
+    targets: Option<(Arc<PersistentDescriptorSet>, Arc<Framebuffer>)>,
+}
+impl CustomPass {
+    /// `camera_buf` is the camera of the context the pass is added to, e.g.
+    /// `SceneRenderer::camera_buf`.
+    ///
+    /// # Safety
+    ///
+    /// The file has to hold valid SPIR-V, see `from_spirv`.
+    pub unsafe fn load(
+        vk_state: &vulkan_helper::VulkanState,
+        camera_buf: Subbuffer<vs::Camera>,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, ShaderError> {
+        let bytes = fs::read(path)?;
+        Self::from_spirv(vk_state, camera_buf, &bytes)
+    }
+
+    /// # Safety
+    ///
+    /// `bytes` has to be valid SPIR-V, e.g. as output by `glslc` or checked
+    /// by `spirv-val`. Only its layout and interface are checked here, the
+    /// instructions are handed to the driver as they are.
+    pub unsafe fn from_spirv(
+        vk_state: &vulkan_helper::VulkanState,
+        camera_buf: Subbuffer<vs::Camera>,
+        bytes: &[u8],
+    ) -> Result<Self, ShaderError> {
+        let words = bytes_to_words(bytes).map_err(|e| ShaderError::Spirv(e.to_string()))?;
+        // SAFETY: the caller guarantees `bytes` is valid SPIR-V.
+        let module = unsafe {
+            ShaderModule::new(vk_state.device.clone(), ShaderModuleCreateInfo::new(&words))
+        }
+        .map_err(|e| ShaderError::Spirv(e.to_string()))?;
+        let fs = module
+            .entry_point("main")
+            .ok_or_else(|| ShaderError::Interface("no main entry point".to_owned()))?;
+        let uses_camera = check_interface(fs.info())?;
+
+        let render_pass = post::get_output_pass(vk_state);
+        let pipeline = post::try_get_fullscreen_pipeline(vk_state, render_pass.clone(), fs, None)
+            .map_err(ShaderError::Pipeline)?;
+
+        Ok(Self {
+            render_pass,
+            pipeline,
+            sampler: post::get_sampler(vk_state, Filter::Linear),
+            camera_buf,
+            uses_camera,
+            targets: None,
+        })
+    }
+}
+impl PostPass for CustomPass {
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    fn set_targets(
+        &mut self,
+        resources: &PostResources,
+        input: Arc<ImageView>,
+        output: Arc<ImageView>,
+    ) {
+        let camera = self
+            .uses_camera
+            .then(|| WriteDescriptorSet::buffer(CAMERA_BINDING, self.camera_buf.clone()));
+        let set = post::get_source_set(
+            resources.set_allocator,
+            &self.pipeline,
+            input,
+            &self.sampler,
+            camera,
+        );
+        let framebuffer = post::get_framebuffer(&self.render_pass, &output);
+        self.targets = Some((set, framebuffer));
+    }
+
+    fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        let (set, framebuffer) = self
+            .targets
+            .as_ref()
+            .expect("Custom pass recorded before its targets were set");
+        post::draw_fullscreen(builder, &self.pipeline, framebuffer, set);
+    }
+}
+
+/// Check a fragment entry point against the interface documented on
+/// `CustomPass`, returning whether it reads the camera.
+fn check_interface(info: &EntryPointInfo) -> Result<bool, ShaderError> {
+    if info.execution_model != ExecutionModel::Fragment {
+        return Err(ShaderError::Interface(format!(
+            "main is a {:?} shader, expected a fragment one",
+            info.execution_model
+        )));
+    }
+    if info.push_constant_requirements.is_some() {
+        return Err(ShaderError::Interface(
+            "push constants aren't supported".to_owned(),
+        ));
+    }
+    if info
+        .input_interface
+        .elements()
+        .iter()
+        .any(|input| input.location != 0 || input.ty != UV_INPUT)
+    {
+        return Err(ShaderError::Interface(
+            "only the vec2 uv input at location 0 is supported".to_owned(),
+        ));
+    }
+    if info
+        .output_interface
+        .elements()
+        .iter()
+        .any(|output| output.location != 0)
+    {
+        return Err(ShaderError::Interface(
+            "only the color output at location 0 is supported".to_owned(),
+        ));
+    }
+
+    let mut uses_source = false;
+    let mut uses_camera = false;
+    for (&(set, binding), requirements) in &info.descriptor_binding_requirements {
+        let expected = match (set, binding) {
+            (0, SOURCE_BINDING) => DescriptorType::CombinedImageSampler,
+            (0, CAMERA_BINDING) => DescriptorType::UniformBuffer,
+            _ => {
+                return Err(ShaderError::Interface(format!(
+                    "unexpected resource at set {set}, binding {binding}"
+                )))
+            }
+        };
+        if !requirements.descriptor_types.contains(&expected) {
+            return Err(ShaderError::Interface(format!(
+                "set {set}, binding {binding} should be a {expected:?}"
+            )));
+        }
+        uses_source |= binding == SOURCE_BINDING;
+        uses_camera |= binding == CAMERA_BINDING;
+    }
+    if !uses_source {
+        return Err(ShaderError::Interface(
+            "the source texture at set 0, binding 0 is never read".to_owned(),
+        ));
+    }
+
+    Ok(uses_camera)
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::{
+        pipeline::layout::PushConstantRange,
+        shader::{
+            DescriptorBindingRequirements, ShaderInterface, ShaderInterfaceEntry, ShaderStages,
+        },
+    };
+
+    use super::*;
+
+    const COLOR_OUTPUT: ShaderInterfaceEntryType = ShaderInterfaceEntryType {
+        num_components: 4,
+        ..UV_INPUT
+    };
+
+    fn interface(entries: &[(u32, ShaderInterfaceEntryType)]) -> ShaderInterface {
+        let elements = entries
+            .iter()
+            .map(|&(location, ty)| ShaderInterfaceEntry {
+                location,
+                index: 0,
+                component: 0,
+                name: None,
+                ty,
+            })
+            .collect();
+        // SAFETY: the tests use one entry per location, of at most 128 bits.
+        unsafe { ShaderInterface::new_unchecked(elements) }
+    }
+
+    fn binding(ty: DescriptorType) -> DescriptorBindingRequirements {
+        DescriptorBindingRequirements {
+            descriptor_types: vec![ty],
+            ..Default::default()
+        }
+    }
+
+    /// Shader only sampling the source.
+    fn grading() -> EntryPointInfo {
+        EntryPointInfo {
+            name: "main".to_owned(),
+            execution_model: ExecutionModel::Fragment,
+            descriptor_binding_requirements: [(
+                (0, SOURCE_BINDING),
+                binding(DescriptorType::CombinedImageSampler),
+            )]
+            .into_iter()
+            .collect(),
+            push_constant_requirements: None,
+            input_interface: interface(&[(0, UV_INPUT)]),
+            output_interface: interface(&[(0, COLOR_OUTPUT)]),
+        }
+    }
+
+    fn is_interface_error(result: Result<bool, ShaderError>) -> bool {
+        matches!(result, Err(ShaderError::Interface(_)))
+    }
+
+    #[test]
+    fn accepts_documented_interface() {
+        assert!(!check_interface(&grading()).unwrap());
+
+        let mut info = grading();
+        info.descriptor_binding_requirements
+            .insert((0, CAMERA_BINDING), binding(DescriptorType::UniformBuffer));
+        assert!(check_interface(&info).unwrap());
+
+        let info = EntryPointInfo {
+            input_interface: interface(&[]),
+            ..grading()
+        };
+        assert!(!check_interface(&info).unwrap());
+    }
+
+    #[test]
+    fn rejects_other_stages() {
+        let info = EntryPointInfo {
+            execution_model: ExecutionModel::Vertex,
+            ..grading()
+        };
+        assert!(is_interface_error(check_interface(&info)));
+    }
+
+    #[test]
+    fn rejects_push_constants() {
+        let info = EntryPointInfo {
+            push_constant_requirements: Some(PushConstantRange {
+                stages: ShaderStages::FRAGMENT,
+                offset: 0,
+                size: 4,
+            }),
+            ..grading()
+        };
+        assert!(is_interface_error(check_interface(&info)));
+    }
+
+    #[test]
+    fn rejects_inputs_not_provided_by_the_vertex_shader() {
+        let extra = EntryPointInfo {
+            input_interface: interface(&[(0, UV_INPUT), (1, UV_INPUT)]),
+            ..grading()
+        };
+        assert!(is_interface_error(check_interface(&extra)));
+
+        let mismatched = EntryPointInfo {
+            input_interface: interface(&[(0, COLOR_OUTPUT)]),
+            ..grading()
+        };
+        assert!(is_interface_error(check_interface(&mismatched)));
+    }
+
+    #[test]
+    fn rejects_extra_outputs() {
+        let info = EntryPointInfo {
+            output_interface: interface(&[(0, COLOR_OUTPUT), (1, COLOR_OUTPUT)]),
+            ..grading()
+        };
+        assert!(is_interface_error(check_interface(&info)));
+    }
+
+    #[test]
+    fn rejects_unexpected_resources() {
+        let mut other_set = grading();
+        other_set
+            .descriptor_binding_requirements
+            .insert((1, 0), binding(DescriptorType::UniformBuffer));
+        assert!(is_interface_error(check_interface(&other_set)));
+
+        let mut wrong_type = grading();
+        wrong_type
+            .descriptor_binding_requirements
+            .insert((0, CAMERA_BINDING), binding(DescriptorType::StorageBuffer));
+        assert!(is_interface_error(check_interface(&wrong_type)));
+
+        let unused_source = EntryPointInfo {
+            descriptor_binding_requirements: Default::default(),
+            ..grading()
+        };
+        assert!(is_interface_error(check_interface(&unused_source)));
+    }
+}
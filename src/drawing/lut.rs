@@ -0,0 +1,393 @@
+use std::{fs, path::Path, sync::Arc};
+
+use thiserror::Error;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferToImageInfo,
+        PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::GraphicsPipeline,
+    render_pass::{Framebuffer, RenderPass},
+    sync::GpuFuture,
+};
+
+use crate::drawing::{
+    context::DrawingContext,
+    lut_fs,
+    post::{self, PostPass, PostResources},
+    vulkan_helper,
+};
+
+/// Bounds of the LUT edge length, in texels.
+const MIN_SIZE: u32 = 2;
+const MAX_SIZE: u32 = 256;
+
+#[derive(Debug, Error)]
+pub enum LutError {
+    #[error("Can't read the LUT file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Can't decode the LUT image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("Invalid LUT: {0}")]
+    Invalid(String),
+}
+
+/// 3D color lookup table, mapping display encoded colors to graded ones.
+///
+/// Texels are stored red first, then green, then blue.
+#[derive(Debug, Clone)]
+pub struct Lut {
+    size: u32,
+    texels: Vec<[u8; 4]>,
+}
+impl Lut {
+    /// Load a `.cube` file, or a strip image for any other extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LutError> {
+        let path = path.as_ref();
+        let is_cube = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("cube"));
+
+        if is_cube {
+            Self::from_cube(&fs::read_to_string(path)?)
+        } else {
+            Self::from_strip(&image::open(path)?.to_rgba8())
+        }
+    }
+
+    /// Parse an Adobe / Resolve `.cube` file, only 3D tables are supported.
+    pub fn from_cube(source: &str) -> Result<Self, LutError> {
+        let invalid = |line: usize, what: &str| LutError::Invalid(format!("line {line}: {what}"));
+        let parse_rgb = |line: usize, values: &[&str]| -> Result<[f32; 3], LutError> {
+            let mut rgb = [0.0; 3];
+            if values.len() != 3 {
+                return Err(invalid(line, "expected 3 values"));
+            }
+            for (value, text) in rgb.iter_mut().zip(values) {
+                *value = text.parse().map_err(|_| invalid(line, "invalid number"))?;
+            }
+            Ok(rgb)
+        };
+
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut values = Vec::new();
+        for (idx, line) in source.lines().enumerate() {
+            let line_nb = idx + 1;
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let Some((&keyword, args)) = words.split_first() else {
+                continue;
+            };
+            match keyword {
+                _ if keyword.starts_with('#') => {}
+                "TITLE" => {}
+                "LUT_1D_SIZE" => return Err(invalid(line_nb, "1D LUTs aren't supported")),
+                "LUT_3D_SIZE" => {
+                    let value = args
+                        .first()
+                        .and_then(|value| value.parse().ok())
+                        .ok_or_else(|| invalid(line_nb, "invalid size"))?;
+                    size = Some(value);
+                }
+                "DOMAIN_MIN" => domain_min = parse_rgb(line_nb, args)?,
+                "DOMAIN_MAX" => domain_max = parse_rgb(line_nb, args)?,
+                // Other keywords, e.g. from vendor extensions, don't change the table.
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {}
+                _ => values.push(parse_rgb(line_nb, &words)?),
+            }
+        }
+
+        let size = size.ok_or_else(|| LutError::Invalid("missing LUT_3D_SIZE".to_owned()))?;
+        let texels = values
+            .into_iter()
+            .map(|rgb| {
+                let [r, g, b] = std::array::from_fn(|channel| {
+                    let range = domain_max[channel] - domain_min[channel];
+                    to_unorm((rgb[channel] - domain_min[channel]) / range)
+                });
+                [r, g, b, u8::MAX]
+            })
+            .collect();
+
+        Self::new(size, texels)
+    }
+
+    /// Read a strip of `size` square slices laid side by side, blue growing
+    /// from one slice to the next, red to the right and green to the bottom.
+    pub fn from_strip(image: &image::RgbaImage) -> Result<Self, LutError> {
+        let size = image.height();
+        if image.width() != size * size {
+            return Err(LutError::Invalid(format!(
+                "a {size} pixels high strip should be {} pixels wide",
+                size * size
+            )));
+        }
+
+        let texels = (0..size)
+            .flat_map(|b| (0..size).flat_map(move |g| (0..size).map(move |r| [r, g, b])))
+            .map(|[r, g, b]| image.get_pixel(b * size + r, g).0)
+            .map(|[r, g, b, _]| [r, g, b, u8::MAX])
+            .collect();
+
+        Self::new(size, texels)
+    }
+
+    fn new(size: u32, texels: Vec<[u8; 4]>) -> Result<Self, LutError> {
+        if !(MIN_SIZE..=MAX_SIZE).contains(&size) {
+            return Err(LutError::Invalid(format!(
+                "size {size} isn't between {MIN_SIZE} and {MAX_SIZE}"
+            )));
+        }
+        if texels.len() != size.pow(3) as usize {
+            return Err(LutError::Invalid(format!(
+                "expected {} entries, found {}",
+                size.pow(3),
+                texels.len()
+            )));
+        }
+
+        Ok(Self { size, texels })
+    }
+
+    /// Copy the table into a 3D image, waiting for the transfer to complete.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn upload(
+        &self,
+        vk_state: &vulkan_helper::VulkanState,
+        ctx: &DrawingContext,
+    ) -> Arc<ImageView> {
+        let staging = Buffer::from_iter(
+            ctx.buffer_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            self.texels.iter().copied(),
+        )
+        .unwrap();
+        let image = Image::new(
+            ctx.buffer_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim3d,
+                format: Format::R8G8B8A8_UNORM,
+                extent: [self.size; 3],
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &ctx.command_buffer_allocator,
+            vk_state.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        builder
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(staging, image.clone()))
+            .unwrap();
+        builder
+            .build()
+            .unwrap()
+            .execute(vk_state.queue.clone())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .and_then(|future| future.wait(None))
+            .expect("Failed to upload the LUT");
+
+        ImageView::new_default(image).unwrap()
+    }
+}
+
+fn to_unorm(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8
+}
+
+/// Color grading applied to the tonemapped image.
+pub struct LutPass {
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    lut: Arc<ImageView>,
+    targets: Option<(Arc<PersistentDescriptorSet>, Arc<Framebuffer>)>,
+}
+impl LutPass {
+    /// `lut` is a table returned by `Lut::upload`.
+    pub fn new(vk_state: &vulkan_helper::VulkanState, lut: Arc<ImageView>) -> Self {
+        let render_pass = post::get_output_pass(vk_state);
+        let fs = lut_fs::load(vk_state.device.clone())
+            .expect("Can't compile LUT fragment shader")
+            .entry_point("main")
+            .unwrap();
+        let pipeline = post::get_fullscreen_pipeline(vk_state, render_pass.clone(), fs, None);
+
+        Self {
+            render_pass,
+            pipeline,
+            sampler: post::get_sampler(vk_state, Filter::Linear),
+            lut,
+            targets: None,
+        }
+    }
+
+    /// Switch to another table. It is used once the post chain has been
+    /// rebound, i.e. when changed from `DrawingContext::edit_post_chain`.
+    pub fn set_lut(&mut self, lut: Arc<ImageView>) {
+        self.lut = lut;
+        self.targets = None;
+    }
+}
+impl PostPass for LutPass {
+    fn name(&self) -> &'static str {
+        "lut"
+    }
+
+    fn set_targets(
+        &mut self,
+        resources: &PostResources,
+        input: Arc<ImageView>,
+        output: Arc<ImageView>,
+    ) {
+        let set = post::get_source_set(
+            resources.set_allocator,
+            &self.pipeline,
+            input,
+            &self.sampler,
+            [WriteDescriptorSet::image_view_sampler(
+                1,
+                self.lut.clone(),
+                self.sampler.clone(),
+            )],
+        );
+        let framebuffer = post::get_framebuffer(&self.render_pass, &output);
+        self.targets = Some((set, framebuffer));
+    }
+
+    fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        let (set, framebuffer) = self
+            .targets
+            .as_ref()
+            .expect("LUT pass recorded before its targets were set");
+        post::draw_fullscreen(builder, &self.pipeline, framebuffer, set);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Identity table of size 2, with a comment and a title to skip.
+    const IDENTITY_CUBE: &str = "# Identity
+TITLE \"identity\"
+LUT_3D_SIZE 2
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    fn assert_invalid(result: Result<Lut, LutError>, what: &str) {
+        match result {
+            Err(LutError::Invalid(message)) => {
+                assert!(
+                    message.contains(what),
+                    "{message:?} doesn't mention {what:?}"
+                )
+            }
+            other => panic!("expected an invalid LUT error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn cube_entries_are_red_first() {
+        let lut = Lut::from_cube(IDENTITY_CUBE).unwrap();
+
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.texels[0], [0, 0, 0, 255]);
+        assert_eq!(lut.texels[1], [255, 0, 0, 255]);
+        assert_eq!(lut.texels[2], [0, 255, 0, 255]);
+        assert_eq!(lut.texels[4], [0, 0, 255, 255]);
+        assert_eq!(lut.texels[7], [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn cube_values_are_scaled_to_the_domain() {
+        let source = IDENTITY_CUBE.replace(
+            "LUT_3D_SIZE 2",
+            "LUT_3D_SIZE 2\nDOMAIN_MIN -1 0 0\nDOMAIN_MAX 1 2 4",
+        );
+        let lut = Lut::from_cube(&source).unwrap();
+
+        assert_eq!(lut.texels[0], [128, 0, 0, 255]);
+        assert_eq!(lut.texels[7], [255, 128, 64, 255]);
+    }
+
+    #[test]
+    fn cube_rejects_missing_entries() {
+        let source = IDENTITY_CUBE.replace("1 1 1\n", "");
+
+        assert_invalid(Lut::from_cube(&source), "expected 8 entries, found 7");
+    }
+
+    #[test]
+    fn cube_rejects_sizes_out_of_range() {
+        assert_invalid(Lut::from_cube("LUT_3D_SIZE 1\n0 0 0\n"), "size 1");
+        assert_invalid(Lut::from_cube("LUT_3D_SIZE 257\n"), "size 257");
+    }
+
+    #[test]
+    fn cube_rejects_1d_tables() {
+        assert_invalid(
+            Lut::from_cube("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n"),
+            "1D LUTs aren't supported",
+        );
+    }
+
+    #[test]
+    fn strip_slices_are_indexed_by_blue() {
+        // Each pixel encodes its own coordinates in the strip.
+        let image = image::RgbaImage::from_fn(4, 2, |x, y| image::Rgba([x as u8, y as u8, 0, 0]));
+        let lut = Lut::from_strip(&image).unwrap();
+
+        assert_eq!(lut.size, 2);
+        // Red to the right, green to the bottom, blue selects the slice.
+        assert_eq!(lut.texels[1], [1, 0, 0, 255]);
+        assert_eq!(lut.texels[2], [0, 1, 0, 255]);
+        assert_eq!(lut.texels[4], [2, 0, 0, 255]);
+        assert_eq!(lut.texels[7], [3, 1, 0, 255]);
+    }
+
+    #[test]
+    fn strip_rejects_mismatched_width() {
+        let image = image::RgbaImage::new(3, 2);
+
+        assert_invalid(Lut::from_strip(&image), "should be 4 pixels wide");
+    }
+
+    #[test]
+    fn strip_rejects_sizes_out_of_range() {
+        let image = image::RgbaImage::new(1, 1);
+
+        assert_invalid(Lut::from_strip(&image), "size 1");
+    }
+}
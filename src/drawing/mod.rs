@@ -1,4 +1,15 @@
+pub mod bloom;
+pub mod capture;
 pub mod context;
+pub mod custom;
+pub mod lut;
+pub mod offscreen;
+pub mod post;
+pub mod profiler;
+pub mod scene;
+pub mod stats;
+pub mod text;
+pub mod tonemap;
 pub mod vulkan_helper;
 
 pub mod vs {
@@ -13,3 +24,51 @@ pub mod fs {
         path: "src/shaders/shader.frag",
     }
 }
+pub mod fullscreen_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shaders/fullscreen.vert",
+    }
+}
+pub mod tonemap_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/tonemap.frag",
+    }
+}
+pub mod bloom_down_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/bloom_down.frag",
+    }
+}
+pub mod bloom_up_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/bloom_up.frag",
+    }
+}
+pub mod present_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/present.frag",
+    }
+}
+pub mod lut_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/lut.frag",
+    }
+}
+pub mod text_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shaders/text.vert",
+    }
+}
+pub mod text_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/text.frag",
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::allocator::{
+        StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo,
+    },
+    image::{sampler::Filter, view::ImageView},
+    memory::allocator::StandardMemoryAllocator,
+    render_pass::{Framebuffer, RenderPass},
+};
+
+use crate::{
+    camera::Projection2D,
+    drawing::{
+        context::{self, BlendMode},
+        post::{self, PostChain, PostResources, PresentPass},
+        scene::SceneRenderer,
+        vulkan_helper,
+    },
+    geometry::Vertex2D,
+};
+
+/// Draws the scene into an image owned by the caller, e.g. when embedded
+/// into another engine, instead of presenting to a window.
+///
+/// `VulkanState::from_device` wraps the device of the host engine. Nothing
+/// is submitted by this context, `record` appends to the caller's command
+/// buffer.
+pub struct OffscreenContext {
+    pub descriptor_set_allocator: StandardDescriptorSetAllocator,
+    pub buffer_allocator: Arc<StandardMemoryAllocator>,
+    /// Image the result is written to, it needs the `COLOR_ATTACHMENT` usage.
+    pub target: Arc<ImageView>,
+    pub render_pass: Arc<RenderPass>,
+    pub framebuffer: Arc<Framebuffer>,
+    /// Scene and post chain, drawn at the size of `target`.
+    pub scene: SceneRenderer,
+    pub present: PresentPass,
+}
+impl OffscreenContext {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn new(vk_state: &vulkan_helper::VulkanState, target: Arc<ImageView>) -> Self {
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(
+            vk_state.device.clone(),
+        ));
+        let descriptor_set_allocator = StandardDescriptorSetAllocator::new(
+            vk_state.device.clone(),
+            StandardDescriptorSetAllocatorCreateInfo::default(),
+        );
+
+        let render_pass = context::get_present_pass(vk_state, target.format());
+        let framebuffer = post::get_framebuffer(&render_pass, &target);
+        let extent = get_extent(&target);
+        let scene = SceneRenderer::new(
+            vk_state,
+            &PostResources {
+                memory_allocator: &memory_allocator,
+                set_allocator: &descriptor_set_allocator,
+            },
+            extent,
+            extent,
+        );
+        let present = PresentPass::new(
+            vk_state,
+            &descriptor_set_allocator,
+            render_pass.clone(),
+            scene.post_output.clone(),
+            Filter::Nearest,
+            !context::is_srgb(target.format()),
+        );
+
+        Self {
+            descriptor_set_allocator,
+            buffer_allocator: memory_allocator,
+            target,
+            render_pass,
+            framebuffer,
+            scene,
+            present,
+        }
+    }
+
+    /// Draw into another image of the same format, e.g. after the host
+    /// engine has been resized. Command buffers have to be recorded again
+    /// afterwards.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn set_target(self, vk_state: &vulkan_helper::VulkanState, target: Arc<ImageView>) -> Self {
+        assert_eq!(
+            target.format(),
+            self.target.format(),
+            "The render pass depends on the target format"
+        );
+
+        let extent = get_extent(&target);
+        let new_framebuffer = post::get_framebuffer(&self.render_pass, &target);
+        let resources = PostResources {
+            memory_allocator: &self.buffer_allocator,
+            set_allocator: &self.descriptor_set_allocator,
+        };
+        let new_scene = self
+            .scene
+            .resize_targets(&resources, extent)
+            .rebuild_pipelines(vk_state, &resources);
+        new_scene.upload_projection(extent);
+
+        Self {
+            target,
+            framebuffer: new_framebuffer,
+            scene: new_scene,
+            ..self
+        }
+        .rebind_present()
+    }
+
+    /// See `SceneRenderer::set_msaa`. Command buffers have to be recorded
+    /// again afterwards.
+    pub fn set_msaa(self, vk_state: &vulkan_helper::VulkanState, samples: u32) -> Self {
+        let resources = PostResources {
+            memory_allocator: &self.buffer_allocator,
+            set_allocator: &self.descriptor_set_allocator,
+        };
+        let new_scene = self.scene.set_msaa(vk_state, &resources, samples);
+
+        Self {
+            scene: new_scene,
+            ..self
+        }
+        .rebind_present()
+    }
+
+    /// See `SceneRenderer::set_depth_test`. Command buffers have to be
+    /// recorded again afterwards.
+    pub fn set_depth_test(self, vk_state: &vulkan_helper::VulkanState, depth_test: bool) -> Self {
+        let resources = PostResources {
+            memory_allocator: &self.buffer_allocator,
+            set_allocator: &self.descriptor_set_allocator,
+        };
+        let new_scene = self.scene.set_depth_test(vk_state, &resources, depth_test);
+
+        Self {
+            scene: new_scene,
+            ..self
+        }
+        .rebind_present()
+    }
+
+    /// See `SceneRenderer::set_clear_color`. Command buffers have to be
+    /// recorded again afterwards.
+    pub fn set_clear_color(self, clear_color: [f32; 4]) -> Self {
+        Self {
+            scene: self.scene.set_clear_color(clear_color),
+            ..self
+        }
+    }
+
+    /// Add, remove or toggle post-processing passes. Command buffers have to
+    /// be recorded again afterwards.
+    pub fn edit_post_chain(self, edit: impl FnOnce(&mut PostChain)) -> Self {
+        let resources = PostResources {
+            memory_allocator: &self.buffer_allocator,
+            set_allocator: &self.descriptor_set_allocator,
+        };
+        let new_scene = self.scene.edit_post_chain(&resources, edit);
+
+        Self {
+            scene: new_scene,
+            ..self
+        }
+        .rebind_present()
+    }
+
+    /// Point the present pass at the current post chain output.
+    fn rebind_present(self) -> Self {
+        let new_present = self.present.rebind_source(
+            &self.descriptor_set_allocator,
+            self.scene.post_output.clone(),
+        );
+
+        Self {
+            present: new_present,
+            ..self
+        }
+    }
+
+    /// Change the world to clip space mapping, for the current target size.
+    pub fn set_projection(&mut self, projection: Projection2D) {
+        self.scene
+            .set_projection(projection, get_extent(&self.target));
+    }
+
+    /// Record the scene, the post chain and the pass writing the result
+    /// into `target`. The caller is responsible for submitting `builder`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        vertex_buf: &Subbuffer<[Vertex2D]>,
+        blend_mode: BlendMode,
+    ) {
+        self.scene
+            .record(builder, vertex_buf, blend_mode, |_, _| {});
+        self.present.record(builder, &self.framebuffer);
+    }
+}
+
+fn get_extent(target: &ImageView) -> [u32; 2] {
+    let [width, height, _] = target.image().extent();
+    [width, height]
+}
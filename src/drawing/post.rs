@@ -0,0 +1,472 @@
+use std::{any::Any, error::Error, sync::Arc};
+
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo,
+        SubpassContents, SubpassEndInfo,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+    },
+    memory::allocator::StandardMemoryAllocator,
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::VertexInputState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    shader::EntryPoint,
+};
+
+use crate::drawing::{context, fullscreen_vs, present_fs, vulkan_helper};
+
+/// Allocators handed to the passes when their targets change.
+pub struct PostResources<'a> {
+    pub memory_allocator: &'a Arc<StandardMemoryAllocator>,
+    pub set_allocator: &'a StandardDescriptorSetAllocator,
+}
+
+/// Fullscreen pass of the post-processing chain.
+///
+/// Passes read the color target written by the previous one and write a new
+/// one, both in `context::HDR_FORMAT`.
+pub trait PostPass: Any {
+    /// Unique name, used to find the pass in the chain and to label its timings.
+    fn name(&self) -> &'static str;
+
+    /// Whether the pass draws over its input instead of writing a new target.
+    fn in_place(&self) -> bool {
+        false
+    }
+
+    /// Called before the first recording and whenever the targets change.
+    /// `output` is `input` for in place passes.
+    fn set_targets(
+        &mut self,
+        resources: &PostResources,
+        input: Arc<ImageView>,
+        output: Arc<ImageView>,
+    );
+
+    fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>);
+}
+
+struct Entry {
+    pass: Box<dyn PostPass>,
+    enabled: bool,
+}
+
+/// Ordered list of post-processing passes, ping-ponging between the scene
+/// target and a spare one.
+///
+/// The chain has to be rebound after being edited, and the command buffers
+/// recorded again.
+pub struct PostChain {
+    entries: Vec<Entry>,
+    spare: Option<Arc<ImageView>>,
+}
+impl Default for PostChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl PostChain {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            spare: None,
+        }
+    }
+
+    pub fn push(&mut self, pass: impl PostPass) {
+        self.insert(self.entries.len(), pass);
+    }
+
+    pub fn insert(&mut self, idx: usize, pass: impl PostPass) {
+        self.entries.insert(
+            idx,
+            Entry {
+                pass: Box::new(pass),
+                enabled: true,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn PostPass>> {
+        let idx = self.position(name)?;
+        Some(self.entries.remove(idx).pass)
+    }
+
+    /// Index of the pass called `name` in the chain.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.pass.name() == name)
+    }
+
+    /// Return `false` if there is no pass called `name`.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let Some(idx) = self.position(name) else {
+            return false;
+        };
+        self.entries[idx].enabled = enabled;
+        true
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.position(name)
+            .is_some_and(|idx| self.entries[idx].enabled)
+    }
+
+    /// First pass of type `T`.
+    pub fn get<T: PostPass>(&self) -> Option<&T> {
+        self.entries.iter().find_map(|entry| {
+            let pass: &dyn Any = entry.pass.as_ref();
+            pass.downcast_ref::<T>()
+        })
+    }
+
+    pub fn get_mut<T: PostPass>(&mut self) -> Option<&mut T> {
+        self.entries.iter_mut().find_map(|entry| {
+            let pass: &mut dyn Any = entry.pass.as_mut();
+            pass.downcast_mut::<T>()
+        })
+    }
+
+    /// Names of the enabled passes, in recording order.
+    pub fn enabled_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.enabled().map(|pass| pass.name())
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &dyn PostPass> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| entry.pass.as_ref())
+    }
+
+    /// Assign targets to the enabled passes, starting from `scene`, and
+    /// return the image holding the final result.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn rebind(&mut self, resources: &PostResources, scene: Arc<ImageView>) -> Arc<ImageView> {
+        let extent = scene.image().extent();
+        let spare = match self.spare.take() {
+            Some(spare) if spare.image().extent() == extent => spare,
+            _ => context::get_hdr_target(resources.memory_allocator, [extent[0], extent[1]]),
+        };
+        self.spare = Some(spare.clone());
+
+        let mut input = scene;
+        let mut free = spare;
+        for entry in self.entries.iter_mut().filter(|entry| entry.enabled) {
+            if entry.pass.in_place() {
+                entry
+                    .pass
+                    .set_targets(resources, input.clone(), input.clone());
+            } else {
+                entry
+                    .pass
+                    .set_targets(resources, input.clone(), free.clone());
+                std::mem::swap(&mut input, &mut free);
+            }
+        }
+
+        input
+    }
+
+    /// Record the enabled passes, `on_pass_end` is called after each of them
+    /// with its index among the enabled passes.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        mut on_pass_end: impl FnMut(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, u32),
+    ) {
+        for (idx, pass) in self.enabled().enumerate() {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("post_pass", name = pass.name()).entered();
+            pass.record(builder);
+            on_pass_end(builder, idx as u32);
+        }
+    }
+}
+
+/// Render pass overwriting a whole post-processing target.
+pub fn get_output_pass(vk_ctx: &vulkan_helper::VulkanState) -> Arc<RenderPass> {
+    vulkano::single_pass_renderpass!(
+        vk_ctx.device.clone(),
+        attachments: {
+            color: {
+                format: context::HDR_FORMAT,
+                samples: 1,
+                load_op: DontCare,
+                store_op: Store,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {},
+        },
+    )
+    .unwrap()
+}
+
+/// Sampler clamping to the edges, suited to read post-processing targets.
+pub fn get_sampler(vk_ctx: &vulkan_helper::VulkanState, filter: Filter) -> Arc<Sampler> {
+    Sampler::new(
+        vk_ctx.device.clone(),
+        SamplerCreateInfo {
+            mag_filter: filter,
+            min_filter: filter,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+/// Pipeline drawing a single fullscreen triangle, the viewport is dynamic so
+/// it doesn't have to be rebuilt when targets are resized.
+pub fn get_fullscreen_pipeline(
+    vk_ctx: &vulkan_helper::VulkanState,
+    render_pass: Arc<RenderPass>,
+    fs: EntryPoint,
+    blend: Option<AttachmentBlend>,
+) -> Arc<GraphicsPipeline> {
+    try_get_fullscreen_pipeline(vk_ctx, render_pass, fs, blend)
+        .expect("Can't create fullscreen pipeline")
+}
+
+/// Same as `get_fullscreen_pipeline`, returning the error when `fs` can't be
+/// used, e.g. for a shader supplied at runtime.
+pub fn try_get_fullscreen_pipeline(
+    vk_ctx: &vulkan_helper::VulkanState,
+    render_pass: Arc<RenderPass>,
+    fs: EntryPoint,
+    blend: Option<AttachmentBlend>,
+) -> Result<Arc<GraphicsPipeline>, Box<dyn Error + Send + Sync>> {
+    let vs = fullscreen_vs::load(vk_ctx.device.clone())
+        .expect("Can't compile fullscreen vertex shader")
+        .entry_point("main")
+        .unwrap();
+
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        vk_ctx.device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(vk_ctx.device.clone())?,
+    )?;
+
+    let subpass = Subpass::from(render_pass, 0).unwrap();
+
+    let pipeline = GraphicsPipeline::new(
+        vk_ctx.device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::default()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend,
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?;
+
+    Ok(pipeline)
+}
+
+pub fn get_framebuffer(render_pass: &Arc<RenderPass>, view: &Arc<ImageView>) -> Arc<Framebuffer> {
+    Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![view.clone()],
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+/// Descriptor set binding `source` at binding 0 of the first set of `pipeline`,
+/// followed by `extra` writes.
+pub fn get_source_set(
+    allocator: &StandardDescriptorSetAllocator,
+    pipeline: &GraphicsPipeline,
+    source: Arc<ImageView>,
+    sampler: &Arc<Sampler>,
+    extra: impl IntoIterator<Item = WriteDescriptorSet>,
+) -> Arc<PersistentDescriptorSet> {
+    let layout = pipeline.layout().set_layouts()[0].clone();
+    PersistentDescriptorSet::new(
+        allocator,
+        layout,
+        [WriteDescriptorSet::image_view_sampler(
+            0,
+            source,
+            sampler.clone(),
+        )]
+        .into_iter()
+        .chain(extra),
+        [],
+    )
+    .unwrap()
+}
+
+/// Record a fullscreen draw covering the whole `framebuffer`, push constants
+/// have to be pushed beforehand.
+pub fn draw_fullscreen<L>(
+    builder: &mut AutoCommandBufferBuilder<L>,
+    pipeline: &Arc<GraphicsPipeline>,
+    framebuffer: &Arc<Framebuffer>,
+    set: &Arc<PersistentDescriptorSet>,
+) {
+    let viewport = Viewport {
+        offset: [0.0, 0.0],
+        extent: framebuffer.extent().map(|x| x as f32),
+        depth_range: 0.0..=1.0,
+    };
+
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo::framebuffer(framebuffer.clone()),
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .and_then(|builder| builder.set_viewport(0, [viewport].into_iter().collect()))
+        .and_then(|builder| builder.bind_pipeline_graphics(pipeline.clone()))
+        .and_then(|builder| {
+            builder.bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                set.clone(),
+            )
+        })
+        .and_then(|builder| builder.draw(3, 1, 0, 0))
+        .and_then(|builder| builder.end_render_pass(SubpassEndInfo::default()))
+        .unwrap();
+}
+
+/// Copy the result of the chain into a swapchain image, scaling it to the
+/// swapchain size.
+pub struct PresentPass {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    source: Arc<ImageView>,
+    set: Arc<PersistentDescriptorSet>,
+    encode_srgb: bool,
+}
+impl PresentPass {
+    /// `encode_srgb` has to be set when the target format isn't sRGB, the
+    /// transfer function is then applied by the shader. `filter` is used
+    /// when the source and target sizes differ.
+    pub fn new(
+        vk_state: &vulkan_helper::VulkanState,
+        set_allocator: &StandardDescriptorSetAllocator,
+        render_pass: Arc<RenderPass>,
+        source: Arc<ImageView>,
+        filter: Filter,
+        encode_srgb: bool,
+    ) -> Self {
+        let fs = present_fs::load(vk_state.device.clone())
+            .expect("Can't compile present fragment shader")
+            .entry_point("main")
+            .unwrap();
+        let pipeline = get_fullscreen_pipeline(vk_state, render_pass, fs, None);
+        let sampler = get_sampler(vk_state, filter);
+        let set = get_source_set(set_allocator, &pipeline, source.clone(), &sampler, []);
+
+        Self {
+            pipeline,
+            sampler,
+            source,
+            set,
+            encode_srgb,
+        }
+    }
+
+    pub fn rebind_source(
+        self,
+        set_allocator: &StandardDescriptorSetAllocator,
+        source: Arc<ImageView>,
+    ) -> Self {
+        let set = get_source_set(
+            set_allocator,
+            &self.pipeline,
+            source.clone(),
+            &self.sampler,
+            [],
+        );
+
+        Self {
+            source,
+            set,
+            ..self
+        }
+    }
+
+    pub fn set_filter(
+        self,
+        vk_state: &vulkan_helper::VulkanState,
+        set_allocator: &StandardDescriptorSetAllocator,
+        filter: Filter,
+    ) -> Self {
+        let sampler = get_sampler(vk_state, filter);
+        let set = get_source_set(
+            set_allocator,
+            &self.pipeline,
+            self.source.clone(),
+            &sampler,
+            [],
+        );
+
+        Self {
+            sampler,
+            set,
+            ..self
+        }
+    }
+
+    pub fn record<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        framebuffer: &Arc<Framebuffer>,
+    ) {
+        builder
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                present_fs::Present {
+                    encode_srgb: self.encode_srgb as u32,
+                },
+            )
+            .unwrap();
+        draw_fullscreen(builder, &self.pipeline, framebuffer, &self.set);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{sync::Arc, time::Duration};
+
+use vulkano::{
+    command_buffer::AutoCommandBufferBuilder,
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    sync::PipelineStage,
+};
+
+use crate::drawing::vulkan_helper;
+
+/// GPU timestamps written around each pass of a recorded frame.
+///
+/// Every recorded command buffer owns a slot of `pass_count + 1` queries: one
+/// written before the first pass and one after each pass. A frame may time
+/// fewer passes than `pass_count`. Results of a slot must only be read once
+/// the frame which used it has been waited on.
+pub struct GpuTimer {
+    query_pool: Arc<QueryPool>,
+    timestamp_period: f32,
+    slot_count: u32,
+    pass_count: u32,
+}
+impl GpuTimer {
+    /// Return `None` when the queue doesn't support timestamps.
+    pub fn new(
+        vk_state: &vulkan_helper::VulkanState,
+        slot_count: u32,
+        pass_count: u32,
+    ) -> Option<Self> {
+        let queue_family_idx = vk_state.queue.queue_family_index() as usize;
+        vk_state.physical_device.queue_family_properties()[queue_family_idx].timestamp_valid_bits?;
+
+        let query_pool = QueryPool::new(
+            vk_state.device.clone(),
+            QueryPoolCreateInfo {
+                query_count: slot_count * (pass_count + 1),
+                ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+            },
+        )
+        .expect("Failed to create timestamp query pool");
+
+        Some(Self {
+            query_pool,
+            timestamp_period: vk_state.physical_device.properties().timestamp_period,
+            slot_count,
+            pass_count,
+        })
+    }
+
+    fn first_query(&self, slot: u32) -> u32 {
+        slot * (self.pass_count + 1)
+    }
+
+    /// Reset the slot and write the starting timestamp, outside of any render pass.
+    ///
+    /// Slots out of the pool range are ignored, which may happen if the
+    /// swapchain grows when recreated.
+    pub fn begin<L>(&self, builder: &mut AutoCommandBufferBuilder<L>, slot: u32) {
+        if slot >= self.slot_count {
+            return;
+        }
+        let first = self.first_query(slot);
+        // SAFETY: the slot is only used by a single command buffer, which is
+        // never resubmitted before the previous submission has been waited on.
+        unsafe {
+            builder
+                .reset_query_pool(self.query_pool.clone(), first..first + self.pass_count + 1)
+                .and_then(|builder| {
+                    builder.write_timestamp(
+                        self.query_pool.clone(),
+                        first,
+                        PipelineStage::TopOfPipe,
+                    )
+                })
+                .unwrap();
+        }
+    }
+
+    /// Write the timestamp marking the end of `pass`, passes beyond the
+    /// capacity of the slot are ignored.
+    pub fn end_pass<L>(&self, builder: &mut AutoCommandBufferBuilder<L>, slot: u32, pass: u32) {
+        if slot >= self.slot_count || pass >= self.pass_count {
+            return;
+        }
+        // SAFETY: see `begin`.
+        unsafe {
+            builder
+                .write_timestamp(
+                    self.query_pool.clone(),
+                    self.first_query(slot) + pass + 1,
+                    PipelineStage::BottomOfPipe,
+                )
+                .unwrap();
+        }
+    }
+
+    /// GPU duration of the first `pass_count` passes recorded in `slot`.
+    ///
+    /// Only passes which have been recorded may be read, otherwise this
+    /// waits for timestamps which are never written.
+    pub fn read(&self, slot: u32, pass_count: u32) -> Option<Vec<Duration>> {
+        if slot >= self.slot_count {
+            return None;
+        }
+        let pass_count = pass_count.min(self.pass_count);
+        let first = self.first_query(slot);
+        let mut timestamps = vec![0u64; pass_count as usize + 1];
+        self.query_pool
+            .get_results(
+                first..first + pass_count + 1,
+                &mut timestamps,
+                QueryResultFlags::WAIT,
+            )
+            .ok()?;
+
+        let durations = timestamps
+            .windows(2)
+            .map(|pair| {
+                let ticks = pair[1].saturating_sub(pair[0]);
+                Duration::from_nanos((ticks as f64 * self.timestamp_period as f64) as u64)
+            })
+            .collect();
+        Some(durations)
+    }
+}
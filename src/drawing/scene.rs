@@ -0,0 +1,370 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo,
+        SubpassContents, SubpassEndInfo,
+    },
+    descriptor_set::PersistentDescriptorSet,
+    format::ClearValue,
+    image::{view::ImageView, SampleCount},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::PipelineBindPoint,
+    render_pass::{Framebuffer, RenderPass},
+    shader::ShaderModule,
+};
+
+use crate::{
+    camera::{Camera2D, Projection2D},
+    drawing::{
+        bloom::{Bloom, BloomPass},
+        context::{self, BlendMode, ScenePipelines},
+        fs,
+        post::{PostChain, PostResources},
+        tonemap::{Tonemap, TonemapPass},
+        vs, vulkan_helper,
+    },
+    geometry::Vertex2D,
+};
+
+/// Scene pass and post-processing chain, drawing into HDR targets owned by
+/// the renderer.
+///
+/// Shared by `DrawingContext` and `OffscreenContext`, which copy
+/// `post_output` to a swapchain image or to an image of the caller. They
+/// have to rebind their copy whenever `post_output` changes, i.e. after any
+/// method taking `self`.
+pub struct SceneRenderer {
+    pub hdr_target: Arc<ImageView>,
+    /// Multisampled attachment resolved into `hdr_target`, when MSAA is enabled.
+    pub msaa_target: Option<Arc<ImageView>>,
+    /// Samples per pixel of the scene pass, see `set_msaa`.
+    pub samples: SampleCount,
+    /// Depth attachment of the scene pass, when the depth test is enabled.
+    pub depth_target: Option<Arc<ImageView>>,
+    /// Whether scene draws are tested against `depth_target`, see `set_depth_test`.
+    pub depth_test: bool,
+    /// Linear color the scene target is cleared to, see `set_clear_color`.
+    pub clear_color: [f32; 4],
+    pub scene_framebuffer: Arc<Framebuffer>,
+    pub scene_pass: Arc<RenderPass>,
+    pub pipelines: ScenePipelines,
+    /// Post-processing applied to `hdr_target`, see `edit_post_chain`.
+    pub post_chain: PostChain,
+    /// Image holding the result of the post chain.
+    pub post_output: Arc<ImageView>,
+    pub camera_buf: Subbuffer<vs::Camera>,
+    pub camera_set: Arc<PersistentDescriptorSet>,
+    pub projection: Projection2D,
+    pub vs: Arc<ShaderModule>,
+    pub fs: Arc<ShaderModule>,
+}
+impl SceneRenderer {
+    /// Draw into targets of `extent` pixels, the projection keeping the
+    /// aspect ratio of `view_extent`, the size the result is displayed at.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn new(
+        vk_state: &vulkan_helper::VulkanState,
+        resources: &PostResources,
+        extent: [u32; 2],
+        view_extent: [u32; 2],
+    ) -> Self {
+        let vs = vs::load(vk_state.device.clone()).expect("Can't compile vertex shader");
+        let fs = fs::load(vk_state.device.clone()).expect("Can't compile fragment shader");
+
+        let samples = SampleCount::Sample1;
+        let scene_pass = context::get_scene_pass(vk_state, samples, false);
+        let hdr_target = context::get_hdr_target(resources.memory_allocator, extent);
+        let scene_framebuffer =
+            context::get_scene_framebuffer(&hdr_target, None, None, &scene_pass);
+        let pipelines = context::get_scene_pipelines(
+            context::get_viewport(&hdr_target),
+            vk_state,
+            scene_pass.clone(),
+            vs.clone(),
+            fs.clone(),
+        );
+        let mut post_chain = PostChain::new();
+        post_chain.push(BloomPass::new(vk_state, resources.memory_allocator.clone()));
+        post_chain.push(TonemapPass::new(
+            vk_state,
+            resources.memory_allocator.clone(),
+        ));
+        let post_output = post_chain.rebind(resources, hdr_target.clone());
+        let projection = Projection2D::default();
+        let camera_buf = Buffer::from_data(
+            resources.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            vs::Camera {
+                view: Camera2D::default().view_matrix(),
+                proj: projection.matrix(view_extent.map(|x| x as f32)),
+            },
+        )
+        .unwrap();
+        let camera_set =
+            context::get_camera_set(resources.set_allocator, &pipelines.opaque, &camera_buf);
+
+        Self {
+            hdr_target,
+            msaa_target: None,
+            samples,
+            depth_target: None,
+            depth_test: false,
+            clear_color: context::DEFAULT_CLEAR_COLOR,
+            scene_framebuffer,
+            scene_pass,
+            pipelines,
+            post_chain,
+            post_output,
+            camera_buf,
+            camera_set,
+            projection,
+            vs,
+            fs,
+        }
+    }
+
+    /// Size of the targets the scene is drawn into.
+    pub fn extent(&self) -> [u32; 2] {
+        let [width, height, _] = self.hdr_target.image().extent();
+        [width, height]
+    }
+
+    /// Recreate the targets with `extent` pixels, the pipelines have to be
+    /// rebuilt afterwards, see `rebuild_pipelines`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn resize_targets(self, resources: &PostResources, extent: [u32; 2]) -> Self {
+        let allocator = resources.memory_allocator;
+        let new_hdr_target = context::get_hdr_target(allocator, extent);
+        let new_msaa_target = context::get_msaa_target(allocator, extent, self.samples);
+        let new_depth_target = self
+            .depth_test
+            .then(|| context::get_depth_target(allocator, extent, self.samples));
+        let new_scene_framebuffer = context::get_scene_framebuffer(
+            &new_hdr_target,
+            new_msaa_target.as_ref(),
+            new_depth_target.as_ref(),
+            &self.scene_pass,
+        );
+
+        Self {
+            hdr_target: new_hdr_target,
+            msaa_target: new_msaa_target,
+            depth_target: new_depth_target,
+            scene_framebuffer: new_scene_framebuffer,
+            ..self
+        }
+        .rebind_post_chain(resources)
+    }
+
+    /// Build the scene pipelines for the current pass and targets.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn rebuild_pipelines(
+        self,
+        vk_state: &vulkan_helper::VulkanState,
+        resources: &PostResources,
+    ) -> Self {
+        let new_pipelines = context::get_scene_pipelines(
+            context::get_viewport(&self.hdr_target),
+            vk_state,
+            self.scene_pass.clone(),
+            self.vs.clone(),
+            self.fs.clone(),
+        );
+        let new_camera_set = context::get_camera_set(
+            resources.set_allocator,
+            &new_pipelines.opaque,
+            &self.camera_buf,
+        );
+
+        Self {
+            pipelines: new_pipelines,
+            camera_set: new_camera_set,
+            ..self
+        }
+    }
+
+    /// Change the number of samples per pixel of the scene pass, clamped to
+    /// what the device supports.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn set_msaa(
+        self,
+        vk_state: &vulkan_helper::VulkanState,
+        resources: &PostResources,
+        samples: u32,
+    ) -> Self {
+        let samples = context::get_sample_count(vk_state, samples);
+        let scene_pass = context::get_scene_pass(vk_state, samples, self.depth_test);
+        let extent = self.extent();
+
+        Self {
+            samples,
+            scene_pass,
+            ..self
+        }
+        .resize_targets(resources, extent)
+        .rebuild_pipelines(vk_state, resources)
+    }
+
+    /// Test scene draws against a depth attachment, so their order doesn't
+    /// matter anymore, see `layer_depth`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn set_depth_test(
+        self,
+        vk_state: &vulkan_helper::VulkanState,
+        resources: &PostResources,
+        depth_test: bool,
+    ) -> Self {
+        let scene_pass = context::get_scene_pass(vk_state, self.samples, depth_test);
+        let extent = self.extent();
+
+        Self {
+            depth_test,
+            scene_pass,
+            ..self
+        }
+        .resize_targets(resources, extent)
+        .rebuild_pipelines(vk_state, resources)
+    }
+
+    /// Background of the scene, in linear HDR values as it goes through the
+    /// post chain.
+    pub fn set_clear_color(self, clear_color: [f32; 4]) -> Self {
+        Self {
+            clear_color,
+            ..self
+        }
+    }
+
+    /// Clear values of the scene pass attachments.
+    pub fn clear_values(&self) -> Vec<Option<ClearValue>> {
+        let mut clear_values = vec![Some(self.clear_color.into())];
+        if self.msaa_target.is_some() {
+            // The resolved attachment is entirely overwritten.
+            clear_values.push(None);
+        }
+        if self.depth_target.is_some() {
+            clear_values.push(Some(ClearValue::Depth(1.0)));
+        }
+        clear_values
+    }
+
+    /// Add, remove or toggle post-processing passes.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn edit_post_chain(
+        mut self,
+        resources: &PostResources,
+        edit: impl FnOnce(&mut PostChain),
+    ) -> Self {
+        edit(&mut self.post_chain);
+        self.rebind_post_chain(resources)
+    }
+
+    fn rebind_post_chain(mut self, resources: &PostResources) -> Self {
+        let post_output = self.post_chain.rebind(resources, self.hdr_target.clone());
+
+        Self {
+            post_output,
+            ..self
+        }
+    }
+
+    /// Upload the camera view matrix, it will be used by the next rendered frame.
+    pub fn set_camera(&self, camera: &Camera2D) {
+        self.camera_buf
+            .write()
+            .expect("Camera buffer is still in use by the GPU")
+            .view = camera.view_matrix();
+    }
+
+    /// Ignored when the bloom pass has been removed from the post chain.
+    pub fn set_bloom(&self, bloom: &Bloom) {
+        if let Some(pass) = self.post_chain.get::<BloomPass>() {
+            pass.set_bloom(bloom);
+        }
+    }
+
+    /// Ignored when the tonemap pass has been removed from the post chain.
+    pub fn set_tonemap(&self, tonemap: &Tonemap) {
+        if let Some(pass) = self.post_chain.get::<TonemapPass>() {
+            pass.set_tonemap(tonemap);
+        }
+    }
+
+    /// Change the world to clip space mapping, for a result displayed at
+    /// `view_extent` pixels.
+    pub fn set_projection(&mut self, projection: Projection2D, view_extent: [u32; 2]) {
+        self.projection = projection;
+        self.upload_projection(view_extent);
+    }
+
+    /// Upload the projection, e.g. after the display size has changed. The
+    /// scene is stretched to it, so it follows its aspect ratio.
+    pub fn upload_projection(&self, view_extent: [u32; 2]) {
+        self.camera_buf
+            .write()
+            .expect("Camera buffer is still in use by the GPU")
+            .proj = self.projection.matrix(view_extent.map(|x| x as f32));
+    }
+
+    /// Record the scene pass drawing `vertex_buf`, then the post chain.
+    /// `on_pass_end` is called after each of them, with 0 for the scene pass
+    /// and the index among the enabled passes plus one for the post chain.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        vertex_buf: &Subbuffer<[Vertex2D]>,
+        blend_mode: BlendMode,
+        mut on_pass_end: impl FnMut(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, u32),
+    ) {
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: self.clear_values(),
+                    ..RenderPassBeginInfo::framebuffer(self.scene_framebuffer.clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .and_then(|builder| {
+                builder.bind_pipeline_graphics(self.pipelines.get(blend_mode).clone())
+            })
+            .and_then(|builder| {
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.pipelines.layout().clone(),
+                    0,
+                    self.camera_set.clone(),
+                )
+            })
+            .and_then(|builder| {
+                builder.push_constants(
+                    self.pipelines.layout().clone(),
+                    0,
+                    vs::Layer {
+                        depth: context::layer_depth(0),
+                    },
+                )
+            })
+            .and_then(|builder| builder.bind_vertex_buffers(0, vertex_buf.clone()))
+            .and_then(|builder| builder.draw(vertex_buf.len() as u32, 1, 0, 0))
+            .and_then(|builder| builder.end_render_pass(SubpassEndInfo::default()))
+            .unwrap();
+        on_pass_end(builder, 0);
+
+        self.post_chain
+            .record(builder, |builder, idx| on_pass_end(builder, idx + 1));
+    }
+}
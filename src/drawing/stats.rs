@@ -0,0 +1,191 @@
+use std::{collections::VecDeque, fmt, time::Duration};
+
+/// Number of frames the rolling averages are computed on.
+const HISTORY_LEN: usize = 120;
+
+/// GPU duration of a named pass.
+#[derive(Debug, Clone, Copy)]
+pub struct PassTiming {
+    pub name: &'static str,
+    pub time: Duration,
+}
+
+/// Measurements of a single frame.
+#[derive(Debug, Clone, Default)]
+pub struct FrameSample {
+    /// Time spent on the CPU to acquire, submit and present the frame.
+    pub cpu_time: Duration,
+    /// Time spent blocked waiting for the GPU to finish the frame.
+    pub gpu_wait_time: Duration,
+    pub draw_count: u32,
+    pub vertex_count: u32,
+    /// Empty when GPU timestamps are not supported.
+    pub gpu_passes: Vec<PassTiming>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    history: VecDeque<FrameSample>,
+    frame_count: u64,
+}
+impl FrameStats {
+    pub fn record(&mut self, sample: FrameSample) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+        self.frame_count += 1;
+    }
+
+    /// Last recorded frame, if any.
+    pub fn last(&self) -> Option<&FrameSample> {
+        self.history.back()
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn average_cpu_time(&self) -> Duration {
+        self.average(|sample| sample.cpu_time)
+    }
+
+    pub fn average_gpu_wait_time(&self) -> Duration {
+        self.average(|sample| sample.gpu_wait_time)
+    }
+
+    /// Average whole frame time, CPU work and GPU wait included.
+    pub fn average_frame_time(&self) -> Duration {
+        self.average(|sample| sample.cpu_time + sample.gpu_wait_time)
+    }
+
+    /// Frames per second sustained by the average frame time, `None` before
+    /// the first frame.
+    pub fn average_fps(&self) -> Option<f32> {
+        let frame_time = self.average_frame_time();
+        (!frame_time.is_zero()).then(|| 1.0 / frame_time.as_secs_f32())
+    }
+
+    /// Average GPU time of the pass called `name`, over the frames which timed it.
+    pub fn average_pass_time(&self, name: &str) -> Option<Duration> {
+        let times = self
+            .history
+            .iter()
+            .flat_map(|sample| sample.gpu_passes.iter())
+            .filter(|pass| pass.name == name)
+            .map(|pass| pass.time)
+            .collect::<Vec<_>>();
+        if times.is_empty() {
+            return None;
+        }
+        Some(times.iter().sum::<Duration>() / times.len() as u32)
+    }
+
+    fn average(&self, field: impl Fn(&FrameSample) -> Duration) -> Duration {
+        if self.history.is_empty() {
+            return Duration::ZERO;
+        }
+        self.history.iter().map(field).sum::<Duration>() / self.history.len() as u32
+    }
+}
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last = self.last().cloned().unwrap_or_default();
+        write!(
+            f,
+            "frame {}: {:.2?} (cpu {:.2?}, gpu wait {:.2?}), {} draws, {} vertices",
+            self.frame_count,
+            self.average_frame_time(),
+            self.average_cpu_time(),
+            self.average_gpu_wait_time(),
+            last.draw_count,
+            last.vertex_count,
+        )?;
+        for pass in &last.gpu_passes {
+            if let Some(time) = self.average_pass_time(pass.name) {
+                write!(f, ", {} {:.2?}", pass.name, time)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Distribution of a set of timings.
+#[derive(Debug, Clone, Copy)]
+pub struct TimingSummary {
+    pub min: Duration,
+    pub average: Duration,
+    pub p99: Duration,
+}
+impl TimingSummary {
+    /// Return `None` when there is no timing to summarize.
+    pub fn new(mut times: Vec<Duration>) -> Option<Self> {
+        if times.is_empty() {
+            return None;
+        }
+        times.sort_unstable();
+
+        let p99_idx = (times.len() * 99).div_ceil(100) - 1;
+        Some(Self {
+            min: times[0],
+            average: times.iter().sum::<Duration>() / times.len() as u32,
+            p99: times[p99_idx],
+        })
+    }
+}
+impl fmt::Display for TimingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "min {:.2?}, avg {:.2?}, p99 {:.2?}",
+            self.min, self.average, self.p99
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(times: impl IntoIterator<Item = u64>) -> Vec<Duration> {
+        times.into_iter().map(Duration::from_millis).collect()
+    }
+
+    #[test]
+    fn summary_of_no_timing() {
+        assert!(TimingSummary::new(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn summary_of_one_timing() {
+        let summary = TimingSummary::new(millis([7])).unwrap();
+        assert_eq!(summary.min, Duration::from_millis(7));
+        assert_eq!(summary.average, Duration::from_millis(7));
+        assert_eq!(summary.p99, Duration::from_millis(7));
+    }
+
+    #[test]
+    fn summary_of_hundred_timings() {
+        // Reversed so the summary has to sort them.
+        let summary = TimingSummary::new(millis((1..=100).rev())).unwrap();
+        assert_eq!(summary.min, Duration::from_millis(1));
+        assert_eq!(summary.average, Duration::from_micros(50_500));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+    }
+
+    #[test]
+    fn average_fps() {
+        let mut stats = FrameStats::default();
+        assert_eq!(stats.average_fps(), None);
+
+        for cpu_time in [8, 12] {
+            stats.record(FrameSample {
+                cpu_time: Duration::from_millis(cpu_time),
+                gpu_wait_time: Duration::from_millis(10),
+                ..Default::default()
+            });
+        }
+        let fps = stats.average_fps().unwrap();
+        assert!((fps - 50.0).abs() < 1e-3, "{fps}");
+    }
+}
@@ -0,0 +1,492 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferToImageInfo, DrawIndirectCommand,
+        PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract, RenderPassBeginInfo,
+        SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    format::Format,
+    image::{sampler::Filter, view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+    },
+    render_pass::{Framebuffer, RenderPass, Subpass},
+    sync::GpuFuture,
+};
+
+use crate::drawing::{
+    context::{DrawingContext, HDR_FORMAT},
+    post::{self, PostPass, PostResources},
+    text_fs, text_vs, vulkan_helper,
+};
+
+/// Glyphs drawn at most, the remaining characters are dropped.
+const MAX_GLYPHS: usize = 1024;
+/// Size of a glyph in font pixels.
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// Distance between two characters and two lines, in font pixels.
+const ADVANCE: [f32; 2] = [6.0, 9.0];
+/// Distance to the top left corner of the target, in target pixels.
+const MARGIN: f32 = 8.0;
+
+/// 5x7 bitmap font, one byte per row from the top, the most significant of
+/// the 5 low bits being the leftmost pixel. Lowercase letters are drawn as
+/// uppercase ones.
+const FONT: &[(char, [u8; 7])] = &[
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('A', [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+];
+
+/// Index of the glyph drawing `c` in `FONT`, `?` when there is none.
+fn get_glyph(c: char) -> u32 {
+    let c = match c {
+        'µ' => 'U',
+        c => c.to_ascii_uppercase(),
+    };
+    FONT.iter().position(|(glyph, _)| *glyph == c).unwrap_or(0) as u32
+}
+
+/// Single row image holding every glyph of `FONT`, each one followed by an
+/// empty column.
+fn get_atlas_texels() -> Vec<u8> {
+    let width = FONT.len() as u32 * (GLYPH_WIDTH + 1);
+    (0..GLYPH_HEIGHT as usize)
+        .flat_map(|row| (0..width).map(move |x| (row, x)))
+        .map(|(row, x)| {
+            let (_, rows) = FONT[(x / (GLYPH_WIDTH + 1)) as usize];
+            let column = x % (GLYPH_WIDTH + 1);
+            let lit = column < GLYPH_WIDTH && rows[row] & (1 << (GLYPH_WIDTH - 1 - column)) != 0;
+            if lit {
+                u8::MAX
+            } else {
+                0
+            }
+        })
+        .collect()
+}
+
+#[derive(BufferContents, Vertex, Clone, Copy)]
+#[repr(C)]
+struct GlyphInstance {
+    /// Top left corner, in target pixels.
+    #[format(R32G32_SFLOAT)]
+    origin: [f32; 2],
+    #[format(R32_UINT)]
+    glyph: u32,
+}
+
+/// Text drawn over the top left corner of its input, e.g. to display frame
+/// statistics.
+///
+/// The text is read from a host visible buffer, so it can be changed with
+/// `set_text` without recording the command buffers again.
+pub struct TextPass {
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+    set: Arc<PersistentDescriptorSet>,
+    glyphs: Subbuffer<[GlyphInstance]>,
+    draw: Subbuffer<[DrawIndirectCommand]>,
+    /// Target pixels per font pixel.
+    scale: f32,
+    framebuffer: Option<Arc<Framebuffer>>,
+}
+impl TextPass {
+    /// Upload the font atlas, waiting for the transfer to complete.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn new(vk_state: &vulkan_helper::VulkanState, ctx: &DrawingContext, scale: f32) -> Self {
+        let render_pass = vulkano::single_pass_renderpass!(
+            vk_state.device.clone(),
+            attachments: {
+                color: {
+                    format: HDR_FORMAT,
+                    samples: 1,
+                    load_op: Load,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )
+        .unwrap();
+        let pipeline = get_pipeline(vk_state, render_pass.clone());
+        let atlas = upload_atlas(vk_state, ctx);
+        let set = PersistentDescriptorSet::new(
+            &ctx.descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                atlas,
+                post::get_sampler(vk_state, Filter::Nearest),
+            )],
+            [],
+        )
+        .unwrap();
+
+        let host_visible = || AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let glyphs = Buffer::new_slice(
+            ctx.buffer_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            host_visible(),
+            MAX_GLYPHS as u64,
+        )
+        .unwrap();
+        let draw = Buffer::from_iter(
+            ctx.buffer_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::INDIRECT_BUFFER,
+                ..Default::default()
+            },
+            host_visible(),
+            [DrawIndirectCommand {
+                vertex_count: 4,
+                instance_count: 0,
+                first_vertex: 0,
+                first_instance: 0,
+            }],
+        )
+        .unwrap();
+
+        Self {
+            render_pass,
+            pipeline,
+            set,
+            glyphs,
+            draw,
+            scale,
+            framebuffer: None,
+        }
+    }
+
+    /// Replace the displayed text, it will be used by the next rendered frame.
+    pub fn set_text(&self, text: &str) {
+        let mut glyphs = self
+            .glyphs
+            .write()
+            .expect("Text buffer is still in use by the GPU");
+        let placed = text
+            .lines()
+            .enumerate()
+            .flat_map(|(row, line)| {
+                line.chars()
+                    .enumerate()
+                    .map(move |(column, c)| (row, column, c))
+            })
+            .filter(|(_, _, c)| !c.is_whitespace())
+            .zip(glyphs.iter_mut())
+            .map(|((row, column, c), glyph)| {
+                *glyph = GlyphInstance {
+                    origin: [
+                        MARGIN + column as f32 * ADVANCE[0] * self.scale,
+                        MARGIN + row as f32 * ADVANCE[1] * self.scale,
+                    ],
+                    glyph: get_glyph(c),
+                };
+            })
+            .count();
+
+        self.draw
+            .write()
+            .expect("Text buffer is still in use by the GPU")[0]
+            .instance_count = placed as u32;
+    }
+}
+impl PostPass for TextPass {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn in_place(&self) -> bool {
+        true
+    }
+
+    fn set_targets(
+        &mut self,
+        _resources: &PostResources,
+        input: Arc<ImageView>,
+        _output: Arc<ImageView>,
+    ) {
+        self.framebuffer = Some(post::get_framebuffer(&self.render_pass, &input));
+    }
+
+    fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        let framebuffer = self
+            .framebuffer
+            .as_ref()
+            .expect("Text pass recorded before its targets were set");
+        let target_size = framebuffer.extent().map(|x| x as f32);
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: target_size,
+            depth_range: 0.0..=1.0,
+        };
+        let params = text_vs::Params {
+            target_size,
+            cell_size: [(GLYPH_WIDTH + 1) as f32, GLYPH_HEIGHT as f32],
+            atlas_size: [
+                (FONT.len() as u32 * (GLYPH_WIDTH + 1)) as f32,
+                GLYPH_HEIGHT as f32,
+            ],
+            scale: self.scale,
+        };
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo::framebuffer(framebuffer.clone()),
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .and_then(|builder| builder.set_viewport(0, [viewport].into_iter().collect()))
+            .and_then(|builder| builder.bind_pipeline_graphics(self.pipeline.clone()))
+            .and_then(|builder| {
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.pipeline.layout().clone(),
+                    0,
+                    self.set.clone(),
+                )
+            })
+            .and_then(|builder| builder.push_constants(self.pipeline.layout().clone(), 0, params))
+            .and_then(|builder| builder.bind_vertex_buffers(0, self.glyphs.clone()))
+            .and_then(|builder| builder.draw_indirect(self.draw.clone()))
+            .and_then(|builder| builder.end_render_pass(SubpassEndInfo::default()))
+            .unwrap();
+    }
+}
+
+/// Pipeline drawing one quad per glyph instance, the viewport is dynamic so
+/// it doesn't have to be rebuilt when targets are resized.
+fn get_pipeline(
+    vk_state: &vulkan_helper::VulkanState,
+    render_pass: Arc<RenderPass>,
+) -> Arc<GraphicsPipeline> {
+    let vs = text_vs::load(vk_state.device.clone())
+        .expect("Can't compile text vertex shader")
+        .entry_point("main")
+        .unwrap();
+    let fs = text_fs::load(vk_state.device.clone())
+        .expect("Can't compile text fragment shader")
+        .entry_point("main")
+        .unwrap();
+
+    let vertex_input_state = GlyphInstance::per_instance()
+        .definition(&vs.info().input_interface)
+        .unwrap();
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        vk_state.device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(vk_state.device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+
+    let subpass = Subpass::from(render_pass, 0).unwrap();
+
+    GraphicsPipeline::new(
+        vk_state.device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState {
+                topology: PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            }),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+fn upload_atlas(vk_state: &vulkan_helper::VulkanState, ctx: &DrawingContext) -> Arc<ImageView> {
+    let staging = Buffer::from_iter(
+        ctx.buffer_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        get_atlas_texels(),
+    )
+    .unwrap();
+    let image = Image::new(
+        ctx.buffer_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R8_UNORM,
+            extent: [FONT.len() as u32 * (GLYPH_WIDTH + 1), GLYPH_HEIGHT, 1],
+            usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &ctx.command_buffer_allocator,
+        vk_state.queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(staging, image.clone()))
+        .unwrap();
+    builder
+        .build()
+        .unwrap()
+        .execute(vk_state.queue.clone())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .and_then(|future| future.wait(None))
+        .expect("Failed to upload the font atlas");
+
+    ImageView::new_default(image).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ATLAS_WIDTH: usize = FONT.len() * (GLYPH_WIDTH as usize + 1);
+
+    /// Lit columns of `row` in the atlas cell of `glyph`, separator included.
+    fn cell_row(texels: &[u8], glyph: u32, row: usize) -> Vec<bool> {
+        let start = row * ATLAS_WIDTH + glyph as usize * (GLYPH_WIDTH as usize + 1);
+        texels[start..start + GLYPH_WIDTH as usize + 1]
+            .iter()
+            .map(|&texel| texel == u8::MAX)
+            .collect()
+    }
+
+    #[test]
+    fn atlas_holds_every_glyph() {
+        let texels = get_atlas_texels();
+
+        assert_eq!(texels.len(), ATLAS_WIDTH * GLYPH_HEIGHT as usize);
+        assert!(texels.iter().all(|&texel| texel == 0 || texel == u8::MAX));
+    }
+
+    #[test]
+    fn atlas_unpacks_glyph_bits_from_the_left() {
+        let texels = get_atlas_texels();
+        let glyph = get_glyph('1');
+
+        // 0x04, 0x0C and 0x0E: the separator column stays empty.
+        assert_eq!(
+            cell_row(&texels, glyph, 0),
+            [false, false, true, false, false, false]
+        );
+        assert_eq!(
+            cell_row(&texels, glyph, 1),
+            [false, true, true, false, false, false]
+        );
+        assert_eq!(
+            cell_row(&texels, glyph, 6),
+            [false, true, true, true, false, false]
+        );
+    }
+
+    #[test]
+    fn glyphs_are_case_insensitive() {
+        assert_eq!(get_glyph('a'), get_glyph('A'));
+        assert_eq!(get_glyph('µ'), get_glyph('U'));
+        assert_eq!(FONT[get_glyph('Z') as usize].0, 'Z');
+    }
+
+    #[test]
+    fn unmapped_characters_fall_back_to_question_mark() {
+        assert_eq!(FONT[0].0, '?');
+        assert_eq!(get_glyph('~'), 0);
+        assert_eq!(get_glyph('é'), 0);
+    }
+}
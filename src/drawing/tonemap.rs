@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    image::{
+        sampler::{Filter, Sampler},
+        view::ImageView,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    pipeline::GraphicsPipeline,
+    render_pass::{Framebuffer, RenderPass},
+};
+
+use crate::drawing::{
+    post::{self, PostPass, PostResources},
+    tonemap_fs, vulkan_helper,
+};
+
+/// Curve mapping HDR radiance to the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tonemapper {
+    /// Clip everything above 1.0.
+    Clamp,
+    #[default]
+    Reinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+impl Tonemapper {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Clamp => Self::Reinhard,
+            Self::Reinhard => Self::Aces,
+            Self::Aces => Self::Clamp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tonemap {
+    pub operator: Tonemapper,
+    /// Linear scale applied to the radiance before the curve.
+    pub exposure: f32,
+}
+impl Default for Tonemap {
+    fn default() -> Self {
+        Self {
+            operator: Tonemapper::default(),
+            exposure: 1.0,
+        }
+    }
+}
+impl Tonemap {
+    fn params(&self) -> tonemap_fs::Params {
+        tonemap_fs::Params {
+            exposure: self.exposure,
+            mode: match self.operator {
+                Tonemapper::Clamp => 0,
+                Tonemapper::Reinhard => 1,
+                Tonemapper::Aces => 2,
+            },
+        }
+    }
+}
+
+/// Resolve the HDR scene into the displayable range, the result stays linear.
+pub struct TonemapPass {
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+    params_buf: Subbuffer<tonemap_fs::Params>,
+    sampler: Arc<Sampler>,
+    targets: Option<(Arc<PersistentDescriptorSet>, Arc<Framebuffer>)>,
+}
+impl TonemapPass {
+    pub fn new(
+        vk_state: &vulkan_helper::VulkanState,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+    ) -> Self {
+        let render_pass = post::get_output_pass(vk_state);
+        let fs = tonemap_fs::load(vk_state.device.clone())
+            .expect("Can't compile tonemap fragment shader")
+            .entry_point("main")
+            .unwrap();
+        let pipeline = post::get_fullscreen_pipeline(vk_state, render_pass.clone(), fs, None);
+        let params_buf = Buffer::from_data(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            Tonemap::default().params(),
+        )
+        .unwrap();
+
+        Self {
+            render_pass,
+            pipeline,
+            params_buf,
+            sampler: post::get_sampler(vk_state, Filter::Linear),
+            targets: None,
+        }
+    }
+
+    /// Upload the tonemapping parameters, used by the next rendered frame.
+    pub fn set_tonemap(&self, tonemap: &Tonemap) {
+        *self
+            .params_buf
+            .write()
+            .expect("Tonemap buffer is still in use by the GPU") = tonemap.params();
+    }
+}
+impl PostPass for TonemapPass {
+    fn name(&self) -> &'static str {
+        "tonemap"
+    }
+
+    fn set_targets(
+        &mut self,
+        resources: &PostResources,
+        input: Arc<ImageView>,
+        output: Arc<ImageView>,
+    ) {
+        let set = post::get_source_set(
+            resources.set_allocator,
+            &self.pipeline,
+            input,
+            &self.sampler,
+            [WriteDescriptorSet::buffer(1, self.params_buf.clone())],
+        );
+        let framebuffer = post::get_framebuffer(&self.render_pass, &output);
+        self.targets = Some((set, framebuffer));
+    }
+
+    fn record(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        let (set, framebuffer) = self
+            .targets
+            .as_ref()
+            .expect("Tonemap pass recorded before its targets were set");
+        post::draw_fullscreen(builder, &self.pipeline, framebuffer, set);
+    }
+}
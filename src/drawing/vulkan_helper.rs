@@ -1,40 +1,231 @@
-use std::sync::Arc;
+use std::{any::Any, sync::Arc};
+
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 
 use vulkano::{
     device::{
         physical::{PhysicalDevice, PhysicalDeviceType},
         Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
     },
-    instance::{Instance, InstanceCreateFlags, InstanceCreateInfo},
+    instance::{Instance, InstanceCreateFlags, InstanceCreateInfo, InstanceExtensions},
     swapchain::Surface,
     VulkanLibrary,
 };
-use winit::window::Window;
 
-fn get_vulkan_instance(window: &Window) -> Arc<Instance> {
+#[cfg(feature = "debug")]
+use vulkano::instance::debug::{
+    DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessenger,
+    DebugUtilsMessengerCallback, DebugUtilsMessengerCreateInfo,
+};
+
+#[cfg(feature = "debug")]
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Window a surface can be created for, from winit, SDL2 or any platform
+/// layer exposing raw window and display handles.
+///
+/// These are the raw-window-handle 0.5 traits, the only ones accepted by
+/// `Surface::from_window` in vulkano 0.34. Windows implementing the 0.6
+/// `HasWindowHandle` and `HasDisplayHandle` traits have to expose the 0.5
+/// ones as well, e.g. through the `rwh_05` feature of winit.
+pub trait WindowHandle: HasRawWindowHandle + HasRawDisplayHandle + Any + Send + Sync {}
+impl<T: HasRawWindowHandle + HasRawDisplayHandle + Any + Send + Sync> WindowHandle for T {}
+
+/// Add the portability enumeration to `extensions` when available, so non
+/// conformant implementations such as MoltenVK are listed. Every instance
+/// has to use it for device indices to match between them.
+fn with_portability(
+    library: &VulkanLibrary,
+    extensions: InstanceExtensions,
+) -> (InstanceExtensions, InstanceCreateFlags) {
+    let khr_portability_enumeration = library.supported_extensions().khr_portability_enumeration;
+    let flags = if khr_portability_enumeration {
+        InstanceCreateFlags::ENUMERATE_PORTABILITY
+    } else {
+        InstanceCreateFlags::empty()
+    };
+
+    (
+        InstanceExtensions {
+            khr_portability_enumeration,
+            ..extensions
+        },
+        flags,
+    )
+}
+
+fn get_vulkan_instance(window: &impl HasRawDisplayHandle) -> Arc<Instance> {
     let library = VulkanLibrary::new().expect("Vulkan library not found");
-    let extensions = Surface::required_extensions(window);
+    #[allow(unused_mut)]
+    let (mut extensions, flags) = with_portability(&library, Surface::required_extensions(window));
+    #[allow(unused_mut)]
+    let mut layers = Vec::new();
+
+    #[cfg(feature = "debug")]
+    {
+        extensions.ext_debug_utils = library.supported_extensions().ext_debug_utils;
+        let has_validation = library
+            .layer_properties()
+            .is_ok_and(|mut layers| layers.any(|layer| layer.name() == VALIDATION_LAYER));
+        if has_validation {
+            layers.push(VALIDATION_LAYER.to_owned());
+        } else {
+            log::warn!("{VALIDATION_LAYER} isn't installed, Vulkan usage won't be validated");
+        }
+    }
+
     Instance::new(
         library,
         InstanceCreateInfo {
-            flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
+            flags,
             enabled_extensions: extensions,
+            enabled_layers: layers,
             ..Default::default()
         },
     )
     .unwrap()
 }
 
+/// Forward validation and driver messages to `log`.
+#[cfg(feature = "debug")]
+fn get_debug_messenger(instance: &Arc<Instance>) -> Option<DebugUtilsMessenger> {
+    if !instance.enabled_extensions().ext_debug_utils {
+        return None;
+    }
+
+    // SAFETY: the callback doesn't call into Vulkan.
+    let callback = unsafe {
+        DebugUtilsMessengerCallback::new(|severity, ty, data| {
+            let level = if severity.intersects(DebugUtilsMessageSeverity::ERROR) {
+                log::Level::Error
+            } else if severity.intersects(DebugUtilsMessageSeverity::WARNING) {
+                log::Level::Warn
+            } else if severity.intersects(DebugUtilsMessageSeverity::INFO) {
+                log::Level::Debug
+            } else {
+                log::Level::Trace
+            };
+            let kind = if ty.intersects(DebugUtilsMessageType::VALIDATION) {
+                "validation"
+            } else if ty.intersects(DebugUtilsMessageType::PERFORMANCE) {
+                "performance"
+            } else {
+                "general"
+            };
+            log::log!(
+                target: "vulkan",
+                level,
+                "[{kind}] {}: {}",
+                data.message_id_name.unwrap_or("unnamed"),
+                data.message
+            );
+        })
+    };
+
+    DebugUtilsMessenger::new(
+        instance.clone(),
+        DebugUtilsMessengerCreateInfo {
+            message_severity: DebugUtilsMessageSeverity::ERROR
+                | DebugUtilsMessageSeverity::WARNING
+                | DebugUtilsMessageSeverity::INFO
+                | DebugUtilsMessageSeverity::VERBOSE,
+            message_type: DebugUtilsMessageType::GENERAL
+                | DebugUtilsMessageType::VALIDATION
+                | DebugUtilsMessageType::PERFORMANCE,
+            ..DebugUtilsMessengerCreateInfo::user_callback(callback)
+        },
+    )
+    .inspect_err(|e| log::warn!("Can't register the Vulkan debug messenger: {e}"))
+    .ok()
+}
+
+/// Physical device the renderer may run on.
+#[derive(Debug, Clone)]
+pub struct Adapter {
+    /// Position in the instance enumeration, as expected by `GpuSelection::Index`.
+    pub index: usize,
+    pub name: String,
+    pub device_type: PhysicalDeviceType,
+}
+
+/// List the physical devices of the system, including the ones which can't
+/// present to a window.
+pub fn enumerate_adapters() -> Vec<Adapter> {
+    let library = VulkanLibrary::new().expect("Vulkan library not found");
+    let (enabled_extensions, flags) = with_portability(&library, InstanceExtensions::empty());
+    let instance = Instance::new(
+        library,
+        InstanceCreateInfo {
+            flags,
+            enabled_extensions,
+            ..Default::default()
+        },
+    )
+    .expect("Can't create vulkan instance");
+    instance
+        .enumerate_physical_devices()
+        .expect("Can't enumerate devices")
+        .enumerate()
+        .map(|(index, device)| Adapter {
+            index,
+            name: device.properties().device_name.clone(),
+            device_type: device.properties().device_type,
+        })
+        .collect()
+}
+
+/// How the physical device is picked among the ones able to present.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum GpuSelection {
+    /// Prefer discrete GPUs, then integrated, virtual and software ones.
+    #[default]
+    Auto,
+    /// Index in the enumeration, see `enumerate_adapters`.
+    Index(usize),
+    /// First device whose name contains this, ignoring case.
+    Name(String),
+}
+impl GpuSelection {
+    /// Read the `RC_GPU_INDEX` or `RC_GPU_NAME` environment variables, the
+    /// index taking precedence.
+    pub fn from_env() -> Self {
+        if let Some(index) = std::env::var("RC_GPU_INDEX")
+            .ok()
+            .and_then(|index| index.parse().ok())
+        {
+            return Self::Index(index);
+        }
+        match std::env::var("RC_GPU_NAME") {
+            Ok(name) if !name.is_empty() => Self::Name(name),
+            _ => Self::Auto,
+        }
+    }
+
+    fn matches(&self, index: usize, device: &PhysicalDevice) -> bool {
+        match self {
+            Self::Auto => true,
+            Self::Index(selected) => *selected == index,
+            Self::Name(name) => device
+                .properties()
+                .device_name
+                .to_lowercase()
+                .contains(&name.to_lowercase()),
+        }
+    }
+}
+
 fn get_best_physical_device(
     instance: &Arc<Instance>,
     surface: &Surface,
     enabled_extensions: &DeviceExtensions,
+    selection: &GpuSelection,
 ) -> (Arc<PhysicalDevice>, u32) {
-    instance
+    let candidates = instance
         .enumerate_physical_devices()
         .expect("Can't enumerate devices")
-        .filter(|device| device.supported_extensions().contains(enabled_extensions))
-        .filter_map(|device| {
+        .enumerate()
+        .filter(|(_, device)| device.supported_extensions().contains(enabled_extensions))
+        .filter_map(|(index, device)| {
             device
                 .queue_family_properties()
                 .iter()
@@ -45,23 +236,78 @@ fn get_best_physical_device(
                             .surface_support(queue_idx as u32, surface)
                             .unwrap_or(false)
                 })
-                .map(|q| (device, q as u32))
+                .map(|q| (index, device, q as u32))
         })
-        .min_by_key(|(p, _)| match p.properties().device_type {
-            PhysicalDeviceType::DiscreteGpu => 0,
-            PhysicalDeviceType::IntegratedGpu => 1,
-            PhysicalDeviceType::VirtualGpu => 2,
-            PhysicalDeviceType::Cpu => 3,
-            _ => 4,
+        .collect::<Vec<_>>();
+
+    let selected = match selection {
+        GpuSelection::Auto => None,
+        _ => {
+            let selected = candidates
+                .iter()
+                .find(|(index, device, _)| selection.matches(*index, device));
+            if selected.is_none() {
+                log::warn!("No suitable GPU matches {selection:?}, picking one automatically");
+            }
+            selected
+        }
+    };
+    let (_, device, queue_family_idx) = selected
+        .or_else(|| {
+            candidates
+                .iter()
+                .min_by_key(|(_, device, _)| get_priority(device))
         })
-        .expect("No physical device found")
+        .expect("No physical device found");
+
+    (device.clone(), *queue_family_idx)
+}
+
+fn get_priority(device: &PhysicalDevice) -> u32 {
+    match device.properties().device_type {
+        PhysicalDeviceType::DiscreteGpu => 0,
+        PhysicalDeviceType::IntegratedGpu => 1,
+        PhysicalDeviceType::VirtualGpu => 2,
+        PhysicalDeviceType::Cpu => 3,
+        _ => 4,
+    }
 }
 
+/// Vertex buffer strides are multiples of this, every attribute being made
+/// of 32-bit components.
+const VERTEX_STRIDE_ALIGNMENT: u32 = 4;
+
+/// Portability devices, e.g. MoltenVK, require `khr_portability_subset` to
+/// be enabled, which forbids the features it lists unless they are enabled.
+/// None of them is enabled since the renderer doesn't rely on any: it only
+/// draws filled triangle lists with vertex attributes inside their stride,
+/// blends with alpha and one factors, has no stencil, mip LOD bias or
+/// comparison sampler, and views single layer images with their own type,
+/// format and identity swizzle. Only the vertex stride alignment limit
+/// applies to it.
 fn get_logical_device(
     physical_device: Arc<PhysicalDevice>,
     enabled_extensions: DeviceExtensions,
     queue_family_idx: u32,
 ) -> (Arc<Device>, Arc<Queue>) {
+    let portability_subset = physical_device
+        .supported_extensions()
+        .khr_portability_subset;
+    if portability_subset {
+        let stride_alignment = physical_device
+            .properties()
+            .min_vertex_input_binding_stride_alignment
+            .unwrap_or(1);
+        assert!(
+            VERTEX_STRIDE_ALIGNMENT.is_multiple_of(stride_alignment),
+            "Vertex strides have to be aligned on {stride_alignment} bytes"
+        );
+    }
+
+    let enabled_extensions = DeviceExtensions {
+        khr_portability_subset: portability_subset,
+        ..enabled_extensions
+    };
     let (device, mut queues) = Device::new(
         physical_device,
         DeviceCreateInfo {
@@ -80,16 +326,26 @@ fn get_logical_device(
 }
 
 /// This struct contains all parts of vulkan API that are not changing.
+///
+/// The window surface isn't part of it, since it may have to be recreated
+/// while the device stays the same, e.g. when the application is suspended.
+/// Several windows can share the same state, each one drawn by its own
+/// `DrawingContext`.
 pub struct VulkanState {
+    #[cfg(feature = "debug")]
+    _debug_messenger: Option<DebugUtilsMessenger>,
     pub instance: Arc<Instance>,
     pub physical_device: Arc<PhysicalDevice>,
     pub device: Arc<Device>,
     pub queue: Arc<Queue>,
-    pub surface: Arc<Surface>,
 }
 impl VulkanState {
-    pub fn new(window: &Arc<Window>) -> Self {
-        let instance = get_vulkan_instance(window);
+    /// `window` is used to pick a device able to present to it.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn new(window: &Arc<impl WindowHandle>, selection: &GpuSelection) -> Self {
+        let instance = get_vulkan_instance(window.as_ref());
+        #[cfg(feature = "debug")]
+        let debug_messenger = get_debug_messenger(&instance);
         let surface = Surface::from_window(instance.clone(), window.clone())
             .expect("Unable to create vulkan surface");
         let enabled_extensions = DeviceExtensions {
@@ -97,7 +353,13 @@ impl VulkanState {
             ..Default::default()
         };
         let (physical_device, queue_family_idx) =
-            get_best_physical_device(&instance, &surface, &enabled_extensions);
+            get_best_physical_device(&instance, &surface, &enabled_extensions, selection);
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            name = %physical_device.properties().device_name,
+            ?selection,
+            "Physical device selected"
+        );
         let (device, queue) = get_logical_device(
             physical_device.clone(),
             enabled_extensions,
@@ -105,11 +367,47 @@ impl VulkanState {
         );
 
         Self {
+            #[cfg(feature = "debug")]
+            _debug_messenger: debug_messenger,
             instance,
             physical_device,
             device,
             queue,
-            surface,
         }
     }
+
+    /// Wrap a device created by another engine. `queue` has to support
+    /// graphics operations.
+    ///
+    /// The debug messenger isn't installed, it belongs to the instance owner.
+    pub fn from_device(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+        assert!(
+            Arc::ptr_eq(queue.device(), &device),
+            "The queue doesn't belong to the device"
+        );
+
+        Self {
+            #[cfg(feature = "debug")]
+            _debug_messenger: None,
+            instance: device.instance().clone(),
+            physical_device: device.physical_device().clone(),
+            device,
+            queue,
+        }
+    }
+
+    /// Create a surface for `window`, it must not outlive the window.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn create_surface(&self, window: Arc<impl WindowHandle>) -> Arc<Surface> {
+        let surface = Surface::from_window(self.instance.clone(), window)
+            .expect("Unable to create vulkan surface");
+        let queue_family_idx = self.queue.queue_family_index();
+        let supported = self
+            .physical_device
+            .surface_support(queue_family_idx, &surface)
+            .unwrap_or(false);
+        assert!(supported, "The rendering queue can't present to the window");
+
+        surface
+    }
 }
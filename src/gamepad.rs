@@ -0,0 +1,26 @@
+use gilrs::{Axis, Gilrs};
+
+/// Stick deflection ignored to absorb controller drift.
+const DEAD_ZONE: f32 = 0.15;
+
+/// Poll connected controllers, the first one with a deflected stick wins.
+pub struct Gamepad {
+    gilrs: Gilrs,
+}
+impl Gamepad {
+    pub fn new() -> Option<Self> {
+        Gilrs::new().ok().map(|gilrs| Self { gilrs })
+    }
+
+    /// Left stick direction, in `[-1, 1]` with the Y axis pointing up.
+    pub fn left_stick(&mut self) -> [f32; 2] {
+        // Events have to be drained for gilrs to refresh the gamepads state.
+        while self.gilrs.next_event().is_some() {}
+
+        self.gilrs
+            .gamepads()
+            .map(|(_, pad)| [pad.value(Axis::LeftStickX), pad.value(Axis::LeftStickY)])
+            .find(|stick| stick.iter().any(|v| v.abs() > DEAD_ZONE))
+            .unwrap_or([0.0, 0.0])
+    }
+}
@@ -1,9 +1,11 @@
 use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex};
 
+use crate::math::Rect;
+
 type Position = [f32; 2];
 type Color = [f32; 3];
 
-#[derive(BufferContents, Vertex)]
+#[derive(BufferContents, Vertex, Clone, Copy)]
 #[repr(C)]
 pub struct Vertex2D {
     #[format(R32G32_SFLOAT)]
@@ -11,6 +13,19 @@ pub struct Vertex2D {
     #[format(R32G32B32_SFLOAT)]
     color: Color,
 }
+impl Vertex2D {
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Position) {
+        self.position = position;
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+}
 impl From<Vertex2DBuilder> for Vertex2D {
     fn from(value: Vertex2DBuilder) -> Self {
         value.build()
@@ -44,3 +59,25 @@ impl Vertex2DBuilder {
         }
     }
 }
+
+/// Find the vertex closest to `point` among those lying within `radius`.
+pub fn pick_vertex(vertices: &[Vertex2D], point: Position, radius: f32) -> Option<usize> {
+    let bounds = Rect::from_center(point, [2.0 * radius; 2]);
+    vertices
+        .iter()
+        .enumerate()
+        .filter(|(_, vertex)| bounds.contains(vertex.position))
+        .map(|(idx, vertex)| {
+            let dx = vertex.position[0] - point[0];
+            let dy = vertex.position[1] - point[1];
+            (idx, dx * dx + dy * dy)
+        })
+        .filter(|(_, dist2)| *dist2 <= radius * radius)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(idx, _)| idx)
+}
+
+/// Bounds of the vertex positions, `None` when there is no vertex.
+pub fn bounds(vertices: &[Vertex2D]) -> Option<Rect> {
+    Rect::bounding(vertices.iter().map(Vertex2D::position))
+}
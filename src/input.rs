@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use winit::{
+    event::KeyEvent,
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+/// Keys bound to application defined actions, e.g. variants of an enum.
+///
+/// Keys are physical ones, so bindings such as WASD keep their layout on
+/// every keyboard. A key triggers one action at most, an action can be
+/// bound to several keys.
+#[derive(Debug, Clone)]
+pub struct KeyMap<A> {
+    bindings: HashMap<KeyCode, A>,
+}
+impl<A> Default for KeyMap<A> {
+    fn default() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+}
+impl<A: Clone> KeyMap<A> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind `key` to `action`, replacing its previous binding.
+    pub fn bind(mut self, key: KeyCode, action: A) -> Self {
+        self.bindings.insert(key, action);
+        self
+    }
+
+    pub fn bind_all(self, keys: impl IntoIterator<Item = KeyCode>, action: A) -> Self {
+        keys.into_iter()
+            .fold(self, |map, key| map.bind(key, action.clone()))
+    }
+
+    pub fn unbind(mut self, key: KeyCode) -> Self {
+        self.bindings.remove(&key);
+        self
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<&A> {
+        self.bindings.get(&key)
+    }
+
+    /// Action triggered by a key press, key releases and unidentified keys
+    /// trigger none.
+    pub fn pressed_action(&self, event: &KeyEvent) -> Option<&A> {
+        if !event.state.is_pressed() {
+            return None;
+        }
+        match event.physical_key {
+            PhysicalKey::Code(code) => self.action(code),
+            PhysicalKey::Unidentified(_) => None,
+        }
+    }
+
+    /// Keys bound to `action`, in no particular order.
+    pub fn keys<'a>(&'a self, action: &'a A) -> impl Iterator<Item = KeyCode> + 'a
+    where
+        A: PartialEq,
+    {
+        self.bindings
+            .iter()
+            .filter(move |(_, bound)| *bound == action)
+            .map(|(key, _)| *key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Action {
+        Quit,
+        Jump,
+    }
+
+    #[test]
+    fn bind_replaces_previous_binding() {
+        let map = KeyMap::new()
+            .bind(KeyCode::Space, Action::Jump)
+            .bind(KeyCode::Space, Action::Quit);
+        assert_eq!(map.action(KeyCode::Space), Some(&Action::Quit));
+        assert_eq!(map.action(KeyCode::Escape), None);
+    }
+
+    #[test]
+    fn several_keys_per_action() {
+        let map = KeyMap::new()
+            .bind_all([KeyCode::Space, KeyCode::KeyW], Action::Jump)
+            .bind(KeyCode::Escape, Action::Quit)
+            .unbind(KeyCode::KeyW);
+        assert_eq!(
+            map.keys(&Action::Jump).collect::<Vec<_>>(),
+            [KeyCode::Space]
+        );
+        assert_eq!(map.action(KeyCode::KeyW), None);
+    }
+}
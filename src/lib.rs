@@ -1,2 +1,10 @@
+pub mod camera;
 pub mod drawing;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
 pub mod geometry;
+#[cfg(feature = "winit")]
+pub mod input;
+pub mod math;
+#[cfg(feature = "winit")]
+pub mod window;
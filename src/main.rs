@@ -1,48 +1,86 @@
 use std::cell::RefCell;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use image::RgbaImage;
 use rand::{thread_rng, Rng};
-use thiserror::Error;
 use vulkano::{
     buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
         AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferInfo, PrimaryAutoCommandBuffer,
-        PrimaryCommandBufferAbstract, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
-        SubpassEndInfo,
+        PrimaryCommandBufferAbstract,
     },
+    image::sampler::Filter,
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
-    swapchain::{self, SwapchainPresentInfo},
-    sync::{self, GpuFuture},
-    Validated, VulkanError,
+    sync::GpuFuture,
 };
 use winit::{
     application::ApplicationHandler,
-    event::{KeyEvent, WindowEvent},
+    dpi::PhysicalPosition,
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::KeyCode,
     window::Window,
 };
 
 use radiance_cascades::{
-    drawing::{context::DrawingContext, vulkan_helper},
-    geometry::{Vertex2D, Vertex2DBuilder},
+    camera::Camera2D,
+    drawing::{
+        bloom::Bloom,
+        context::{
+            BlendMode, DrawingContext, DrawingError, PresentModePreference, RenderResolution,
+        },
+        custom::CustomPass,
+        lut::{Lut, LutPass},
+        stats::{FrameSample, FrameStats, PassTiming, TimingSummary},
+        text::TextPass,
+        tonemap::Tonemap,
+        vulkan_helper::{self, GpuSelection},
+    },
+    geometry::{self, Vertex2D, Vertex2DBuilder},
+    input::KeyMap,
+    window::{self as rc_window, WindowConfig},
 };
 
-fn new_window(event_loop: &ActiveEventLoop) -> Arc<Window> {
+/// Distance, in world units, under which a click grabs a vertex.
+const PICK_RADIUS: f32 = 0.05;
+const ZOOM_STEP: f32 = 1.1;
+/// Distance, in world units at zoom 1, travelled by a key press.
+const MOVE_STEP: f32 = 0.05;
+const EXPOSURE_STEP: f32 = 1.25;
+const BLOOM_STEP: f32 = 1.25;
+const TITLE_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+/// Screen pixels per font pixel of the statistics overlay.
+const TEXT_SCALE: f32 = 2.0;
+/// Consecutive device losses after which the demo gives up.
+const MAX_RECOVERY_ATTEMPTS: u32 = 3;
+/// Simulation step of the continuous mode.
+const UPDATE_STEP: Duration = Duration::from_micros(16_667);
+/// Steps run before a frame at most, the remaining time is dropped when
+/// rendering can't keep up.
+const MAX_UPDATES_PER_FRAME: u32 = 5;
+/// Camera rotation speed while spinning, in radians per second.
+const SPIN_SPEED: f32 = 0.5;
+#[cfg(feature = "gamepad")]
+const GAMEPAD_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(16);
+
+fn new_window(event_loop: &ActiveEventLoop, config: &WindowConfig) -> Arc<Window> {
     let window = event_loop
-        .create_window(Window::default_attributes())
+        .create_window(config.attributes())
         .expect("Can't create window");
     Arc::new(window)
 }
 
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
 fn get_command_buffers(
     vk_ctx: &vulkan_helper::VulkanState,
     ctx: &DrawingContext,
     vertex_buf: &Subbuffer<[Vertex2D]>,
+    blend_mode: BlendMode,
 ) -> Box<[Arc<PrimaryAutoCommandBuffer>]> {
-    ctx.framebuffers
-        .iter()
-        .map(|framebuf| {
+    (0..ctx.framebuffers.len())
+        .map(|idx| {
             let mut builder = AutoCommandBufferBuilder::primary(
                 &ctx.command_buffer_allocator,
                 vk_ctx.queue.queue_family_index(),
@@ -50,62 +88,477 @@ fn get_command_buffers(
             )
             .unwrap();
 
-            builder
-                .begin_render_pass(
-                    RenderPassBeginInfo {
-                        clear_values: vec![Some([0.1, 0.1, 0.1, 0.1].into())],
-                        ..RenderPassBeginInfo::framebuffer(framebuf.clone())
-                    },
-                    SubpassBeginInfo {
-                        contents: SubpassContents::Inline,
-                        ..Default::default()
-                    },
-                )
-                .and_then(|builder| builder.bind_pipeline_graphics(ctx.pipeline.clone()))
-                .and_then(|builder| builder.bind_vertex_buffers(0, vertex_buf.clone()))
-                .and_then(|builder| builder.draw(vertex_buf.len() as u32, 1, 0, 0))
-                .and_then(|builder| builder.end_render_pass(SubpassEndInfo::default()))
-                .unwrap();
+            ctx.record(&mut builder, idx as u32, vertex_buf, blend_mode);
 
             builder.build().unwrap()
         })
         .collect()
 }
 
-#[derive(Debug, Error)]
-enum DrawingError {
-    #[error("Swapchain needs to be recreate")]
-    ObsoleteSwapchain,
+fn random_triangles(count: usize) -> Vec<Vertex2D> {
+    let rng = RefCell::new(thread_rng());
+    let gen_coords = || {
+        let mut rng = rng.borrow_mut();
+        [rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)]
+    };
+    let gen_color = || {
+        let mut rng = rng.borrow_mut();
+        [rng.gen(), rng.gen(), rng.gen()]
+    };
+
+    (0..count * 3)
+        .map(|_| {
+            Vertex2DBuilder::new(gen_coords())
+                .color(gen_color())
+                .build()
+        })
+        .collect()
+}
+
+/// Render a fixed number of frames into numbered PNG files, then exit.
+struct Recording {
+    dir: PathBuf,
+    frame_count: u32,
+    recorded: u32,
+}
+
+/// Render a fixed number of frames of a stress scene, then print timings.
+struct Benchmark {
+    frame_count: u32,
+    samples: Vec<FrameSample>,
+}
+
+/// Fixed-timestep updates of the continuous mode, redrawing every frame.
+struct Simulation {
+    last_tick: Instant,
+    /// Time left to simulate, less than `UPDATE_STEP` once caught up.
+    accumulator: Duration,
+    /// Camera before the last step, blended with `App::camera` when drawing.
+    previous_camera: Camera2D,
+    spinning: bool,
+    /// Time is frozen and frames are only drawn on demand, see `App::step_frame`.
+    paused: bool,
+}
+impl Simulation {
+    fn new(camera: Camera2D) -> Self {
+        Self {
+            last_tick: Instant::now(),
+            accumulator: Duration::ZERO,
+            previous_camera: camera,
+            spinning: false,
+            paused: false,
+        }
+    }
+
+    fn step(&mut self, camera: &mut Camera2D) {
+        self.previous_camera = *camera;
+        if self.spinning {
+            camera.rotation += SPIN_SPEED * UPDATE_STEP.as_secs_f32();
+        }
+    }
+}
+
+/// Commands of the demo, triggered through `App::key_map`.
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Quit,
+    ToggleSecondaryView,
+    ToggleStats,
+    ToggleFullscreen,
+    Screenshot,
+    ScaleExposure(f32),
+    CycleTonemap,
+    ScaleBloom(f32),
+    TogglePostPass(&'static str),
+    ToggleUpscaleFilter,
+    CycleBlendMode,
+    CyclePresentMode,
+    ToggleSpin,
+    TogglePause,
+    StepFrame,
+    RandomizeTriangle,
+    /// Move the selected vertex or the camera, in world units at zoom 1.
+    Move([f32; 2]),
+}
+
+fn default_key_map() -> KeyMap<Action> {
+    KeyMap::new()
+        .bind(KeyCode::Escape, Action::Quit)
+        .bind(KeyCode::F2, Action::ToggleSecondaryView)
+        .bind(KeyCode::F3, Action::ToggleStats)
+        .bind(KeyCode::F11, Action::ToggleFullscreen)
+        .bind(KeyCode::F12, Action::Screenshot)
+        .bind(KeyCode::Equal, Action::ScaleExposure(EXPOSURE_STEP))
+        .bind(KeyCode::Minus, Action::ScaleExposure(1.0 / EXPOSURE_STEP))
+        .bind(KeyCode::KeyT, Action::CycleTonemap)
+        .bind(KeyCode::BracketRight, Action::ScaleBloom(BLOOM_STEP))
+        .bind(KeyCode::BracketLeft, Action::ScaleBloom(1.0 / BLOOM_STEP))
+        .bind(KeyCode::KeyB, Action::TogglePostPass("bloom"))
+        .bind(KeyCode::KeyL, Action::TogglePostPass("lut"))
+        .bind(KeyCode::KeyN, Action::ToggleUpscaleFilter)
+        .bind(KeyCode::KeyM, Action::CycleBlendMode)
+        .bind(KeyCode::KeyV, Action::CyclePresentMode)
+        .bind(KeyCode::KeyR, Action::ToggleSpin)
+        .bind(KeyCode::KeyP, Action::TogglePause)
+        .bind(KeyCode::Period, Action::StepFrame)
+        .bind(KeyCode::Space, Action::RandomizeTriangle)
+        .bind_all(
+            [KeyCode::ArrowUp, KeyCode::KeyW],
+            Action::Move([0.0, MOVE_STEP]),
+        )
+        .bind_all(
+            [KeyCode::ArrowDown, KeyCode::KeyS],
+            Action::Move([0.0, -MOVE_STEP]),
+        )
+        .bind_all(
+            [KeyCode::ArrowLeft, KeyCode::KeyA],
+            Action::Move([-MOVE_STEP, 0.0]),
+        )
+        .bind_all(
+            [KeyCode::ArrowRight, KeyCode::KeyD],
+            Action::Move([MOVE_STEP, 0.0]),
+        )
+}
+
+/// Triangles drawn by the benchmark scene.
+const BENCH_TRIANGLES: usize = 20_000;
+
+struct Options {
+    recording: Option<Recording>,
+    bench: Option<Benchmark>,
+    /// Color grading table applied after tonemapping.
+    lut: Option<PathBuf>,
+    /// SPIR-V fragment shader run at the end of the post chain, see `CustomPass`.
+    composite_shader: Option<PathBuf>,
+    resolution: RenderResolution,
+    /// Samples per pixel of the scene pass.
+    msaa: u32,
+    depth_test: bool,
+    /// Background of the scene, `DEFAULT_CLEAR_COLOR` when unset.
+    clear_color: Option<[f32; 4]>,
+    present_mode: PresentModePreference,
+    /// Swapchain images, one more than the surface minimum when unset.
+    image_count: Option<u32>,
+    gpu: GpuSelection,
+    /// Print the available GPUs instead of running the demo.
+    list_gpus: bool,
+    /// Redraw continuously instead of waiting for events.
+    continuous: bool,
+    title: Option<String>,
+    /// Show the frame rate and frame time in the window title.
+    title_stats: bool,
+}
+
+/// Live statistics appended to the window title.
+struct TitleStats {
+    title: String,
+    last_update: Instant,
+}
+
+const USAGE: &str = "Usage: radiance_cascades [--record <frame count> [--output <dir>]] \
+                     [--bench <frame count>] [--lut <.cube or strip image>] \
+                     [--composite-shader <.spv>] \
+                     [--resolution <width>x<height> | <scale>] [--msaa <samples>] [--depth-test] \
+                     [--clear-color <r>,<g>,<b>[,<a>]] \
+                     [--present-mode <immediate|mailbox|fifo|fifo-relaxed>] \
+                     [--swapchain-images <count>] \
+                     [--gpu <index or name>] [--list-gpus] [--continuous] \
+                     [--title <text>] [--title-stats] [--help]
+
+--bench draws into the window rather than offscreen, so its timings include
+presenting to the surface.";
+
+fn parse_count(count: String) -> Result<u32, String> {
+    match count.parse::<u32>() {
+        Ok(0) => Err("Frame count must be positive".to_owned()),
+        Ok(count) => Ok(count),
+        Err(e) => Err(format!("Invalid frame count {count}: {e}")),
+    }
+}
+
+fn parse_image_count(count: String) -> Result<u32, String> {
+    match count.parse::<u32>() {
+        Ok(0) => Err("Swapchain image count must be positive".to_owned()),
+        Ok(count) => Ok(count),
+        Err(e) => Err(format!("Invalid image count {count}: {e}")),
+    }
+}
+
+/// MSAA samples per pixel, a power of two.
+fn parse_samples(samples: String) -> Result<u32, String> {
+    match samples.parse::<u32>() {
+        Ok(samples) if samples.is_power_of_two() => Ok(samples),
+        Ok(_) => Err(format!(
+            "Invalid sample count {samples}: must be a power of two"
+        )),
+        Err(e) => Err(format!("Invalid sample count {samples}: {e}")),
+    }
+}
+
+/// Either a fixed size such as `640x360`, or a factor of the window size.
+fn parse_resolution(resolution: String) -> Result<RenderResolution, String> {
+    let invalid = |e: &dyn std::fmt::Display| format!("Invalid resolution {resolution}: {e}");
+    match resolution.split_once('x') {
+        Some((width, height)) => {
+            let width = width.parse::<u32>().map_err(|e| invalid(&e))?;
+            let height = height.parse::<u32>().map_err(|e| invalid(&e))?;
+            if width == 0 || height == 0 {
+                return Err(invalid(&"size can't be zero"));
+            }
+            Ok(RenderResolution::Fixed([width, height]))
+        }
+        None => {
+            let scale = resolution.parse::<f32>().map_err(|e| invalid(&e))?;
+            if scale.is_nan() || scale <= 0.0 {
+                return Err(invalid(&"scale must be positive"));
+            }
+            Ok(RenderResolution::Scaled(scale))
+        }
+    }
+}
+
+/// Comma separated linear components, alpha defaulting to 1.
+fn parse_color(color: String) -> Result<[f32; 4], String> {
+    let components = color
+        .split(',')
+        .map(|component| component.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid color {color}: {e}"))?;
+    match components[..] {
+        [r, g, b] => Ok([r, g, b, 1.0]),
+        [r, g, b, a] => Ok([r, g, b, a]),
+        _ => Err(format!("Invalid color {color}: expected 3 or 4 components")),
+    }
+}
+
+fn parse_present_mode(mode: String) -> Result<PresentModePreference, String> {
+    match mode.as_str() {
+        "immediate" => Ok(PresentModePreference::Immediate),
+        "mailbox" => Ok(PresentModePreference::Mailbox),
+        "fifo" => Ok(PresentModePreference::Fifo),
+        "fifo-relaxed" => Ok(PresentModePreference::FifoRelaxed),
+        _ => Err(format!("Unknown present mode {mode}")),
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut frame_count = None;
+    let mut bench_frame_count = None;
+    let mut dir = PathBuf::from("frames");
+    let mut lut = None;
+    let mut composite_shader = None;
+    let mut resolution = RenderResolution::default();
+    let mut msaa = 1;
+    let mut depth_test = false;
+    let mut clear_color = None;
+    let mut present_mode = None;
+    let mut image_count = None;
+    let mut gpu = None;
+    let mut list_gpus = false;
+    let mut continuous = false;
+    let mut title = None;
+    let mut title_stats = false;
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--record" => frame_count = Some(parse_count(value()?)?),
+            "--bench" => bench_frame_count = Some(parse_count(value()?)?),
+            "--output" => dir = value()?.into(),
+            "--lut" => lut = Some(value()?.into()),
+            "--composite-shader" => composite_shader = Some(value()?.into()),
+            "--resolution" => resolution = parse_resolution(value()?)?,
+            "--list-gpus" => list_gpus = true,
+            "--depth-test" => depth_test = true,
+            "--clear-color" => clear_color = Some(parse_color(value()?)?),
+            "--continuous" => continuous = true,
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            "--title" => title = Some(value()?),
+            "--title-stats" => title_stats = true,
+            "--gpu" => {
+                let gpu_value = value()?;
+                gpu = Some(match gpu_value.parse::<usize>() {
+                    Ok(index) => GpuSelection::Index(index),
+                    Err(_) => GpuSelection::Name(gpu_value),
+                });
+            }
+            "--present-mode" => present_mode = Some(parse_present_mode(value()?)?),
+            "--swapchain-images" => image_count = Some(parse_image_count(value()?)?),
+            "--msaa" => msaa = parse_samples(value()?)?,
+            _ => return Err(format!("Unknown argument {arg}")),
+        }
+    }
+
+    let recording = frame_count.map(|frame_count| Recording {
+        dir,
+        frame_count,
+        recorded: 0,
+    });
+    let bench = bench_frame_count.map(|frame_count| Benchmark {
+        frame_count,
+        samples: Vec::with_capacity(frame_count as usize),
+    });
+    if recording.is_some() && bench.is_some() {
+        return Err("--record and --bench can't be used together".to_owned());
+    }
+
+    // Benchmarks measure the renderer, not the display refresh rate.
+    let present_mode = present_mode.unwrap_or(match bench {
+        Some(_) => PresentModePreference::Immediate,
+        None => PresentModePreference::default(),
+    });
+
+    Ok(Options {
+        recording,
+        bench,
+        lut,
+        composite_shader,
+        resolution,
+        msaa,
+        depth_test,
+        clear_color,
+        present_mode,
+        image_count,
+        gpu: gpu.unwrap_or_else(GpuSelection::from_env),
+        list_gpus,
+        continuous,
+        title,
+        title_stats,
+    })
+}
+
+/// Additional window drawing the scene, sharing the device of the main one.
+struct SecondaryView {
+    window: Arc<Window>,
+    ctx: DrawingContext,
+    command_buffers: Box<[Arc<PrimaryAutoCommandBuffer>]>,
 }
 
 #[derive(Default)]
 struct App {
+    window_config: WindowConfig,
+    key_map: KeyMap<Action>,
+    recording: Option<Recording>,
+    bench: Option<Benchmark>,
+    lut_path: Option<PathBuf>,
+    composite_shader_path: Option<PathBuf>,
+    resolution: RenderResolution,
+    msaa: u32,
+    depth_test: bool,
+    clear_color: Option<[f32; 4]>,
+    present_mode: PresentModePreference,
+    image_count: Option<u32>,
+    gpu: GpuSelection,
+    #[cfg(feature = "gamepad")]
+    gamepad: Option<radiance_cascades::gamepad::Gamepad>,
     window: Option<Arc<Window>>,
     vk_state: Option<vulkan_helper::VulkanState>,
     ctx: Option<DrawingContext>,
+    secondary: Option<SecondaryView>,
     command_buffers: Option<Box<[Arc<PrimaryAutoCommandBuffer>]>>,
     vertex_buf: Option<Subbuffer<[Vertex2D]>>,
+    /// Copy of `vertex_buf`, uploaded again when the renderer is rebuilt.
+    vertices: Vec<Vertex2D>,
+    camera: Camera2D,
+    /// Blending of the triangles with each other.
+    blend_mode: BlendMode,
+    simulation: Option<Simulation>,
+    title_stats: Option<TitleStats>,
+    tonemap: Tonemap,
+    bloom: Bloom,
+    stats: FrameStats,
+    cursor_position: Option<PhysicalPosition<f64>>,
+    selected_vertex: Option<usize>,
+    dragging: bool,
+    panning: bool,
     window_resized: bool,
     need_recreate_swapchain: bool,
+    need_rebuild_renderer: bool,
+    /// Set when only the surface has been lost, the device is kept.
+    need_rebuild_surface: bool,
+    recovery_attempts: u32,
 }
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        self.init(event_loop);
+        if self.vk_state.is_none() {
+            self.init(event_loop);
+            return;
+        }
+
+        self.window = Some(new_window(event_loop, &self.window_config));
+        self.build_context();
+        self.window.as_ref().unwrap().request_redraw();
+    }
+
+    /// The surface may be destroyed by the platform once suspended, drop
+    /// everything presenting to it but keep the device.
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        self.secondary = None;
+        self.command_buffers = None;
+        self.vertex_buf = None;
+        self.ctx = None;
+        self.window = None;
+    }
+
+    #[cfg(feature = "gamepad")]
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_none() {
+            return;
+        }
+        let Some(gamepad) = self.gamepad.as_mut() else {
+            return;
+        };
+
+        let [x, y] = gamepad.left_stick();
+        if x != 0.0 || y != 0.0 {
+            self.move_selection([x * MOVE_STEP / 2.0, y * MOVE_STEP / 2.0]);
+        }
+        event_loop.set_control_flow(ControlFlow::wait_duration(GAMEPAD_POLL_INTERVAL));
     }
 
     fn window_event(
         &mut self,
         event_loop: &winit::event_loop::ActiveEventLoop,
-        _window_id: winit::window::WindowId,
+        window_id: winit::window::WindowId,
         event: winit::event::WindowEvent,
     ) {
+        if self
+            .secondary
+            .as_ref()
+            .is_some_and(|view| view.window.id() == window_id)
+        {
+            self.secondary_window_event(event);
+            return;
+        }
+
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::RedrawRequested
+                if !rc_window::is_drawable(self.window.as_ref().unwrap()) => {}
+            WindowEvent::RedrawRequested if self.bench.is_some() => self.bench_frame(event_loop),
+            WindowEvent::RedrawRequested if self.recording.is_some() => {
+                self.record_frame(event_loop)
+            }
             WindowEvent::RedrawRequested => {
-                let res = self.render();
-                match res {
-                    Err(DrawingError::ObsoleteSwapchain) => self.need_recreate_swapchain = true,
-                    _ => {}
+                self.advance_simulation();
+                match self.render() {
+                    Ok(()) => self.recovery_attempts = 0,
+                    Err(e) => self.schedule_recovery(&e),
+                }
+                self.update_overlay();
+                self.update_title();
+                if self
+                    .simulation
+                    .as_ref()
+                    .is_some_and(|simulation| !simulation.paused)
+                {
+                    self.request_redraw();
                 }
             }
             WindowEvent::Resized(_) => self.window_resized = true,
@@ -113,36 +566,73 @@ impl ApplicationHandler for App {
                 device_id: _,
                 event,
                 is_synthetic: _,
-            } => self.handle_input(event),
+            } => self.handle_input(event_loop, event),
+            WindowEvent::CursorMoved {
+                device_id: _,
+                position,
+            } => self.handle_cursor_moved(position),
+            WindowEvent::MouseInput {
+                device_id: _,
+                state,
+                button,
+            } => self.handle_mouse_input(state, button),
+            WindowEvent::MouseWheel {
+                device_id: _,
+                delta,
+                phase: _,
+            } => self.handle_mouse_wheel(delta),
             _ => {}
         }
 
-        if self.window_resized {
-            self.recreate_swapchain();
-            self.resize_viewport();
+        // Recreation is postponed until the window is restored.
+        if !rc_window::is_drawable(self.window.as_ref().unwrap()) {
+            return;
+        }
+        if self.need_rebuild_renderer || self.need_rebuild_surface {
+            self.rebuild_renderer(event_loop);
+            return;
         }
-        if self.need_recreate_swapchain {
+        if self.window_resized || self.need_recreate_swapchain {
             self.recreate_swapchain();
+            self.window.as_ref().unwrap().request_redraw();
         }
     }
 }
 impl App {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     fn init(&mut self, event_loop: &ActiveEventLoop) {
-        let window = new_window(event_loop);
-        let vk_state = vulkan_helper::VulkanState::new(&window);
-        let ctx = DrawingContext::new(&vk_state, &window);
-
-        let vertexes = [
-            Vertex2DBuilder::new([-0.5, -0.5])
-                .color([1.0, 0.0, 0.0])
-                .build(),
-            Vertex2DBuilder::new([0.0, 0.5])
-                .color([0.0, 1.0, 0.0])
-                .build(),
-            Vertex2DBuilder::new([0.5, -0.25])
-                .color([0.0, 0.0, 1.0])
-                .build(),
-        ];
+        self.vertices = match self.bench {
+            Some(_) => random_triangles(BENCH_TRIANGLES),
+            None => vec![
+                Vertex2DBuilder::new([-0.5, -0.5])
+                    .color([1.0, 0.0, 0.0])
+                    .build(),
+                Vertex2DBuilder::new([0.0, 0.5])
+                    .color([0.0, 1.0, 0.0])
+                    .build(),
+                Vertex2DBuilder::new([0.5, -0.25])
+                    .color([0.0, 0.0, 1.0])
+                    .build(),
+            ],
+        };
+
+        #[cfg(feature = "gamepad")]
+        {
+            self.gamepad = radiance_cascades::gamepad::Gamepad::new();
+        }
+
+        let window = new_window(event_loop, &self.window_config);
+        self.vk_state = Some(vulkan_helper::VulkanState::new(&window, &self.gpu));
+        self.window = Some(window);
+        self.build_context();
+    }
+
+    /// Create everything depending on the window surface, from the current
+    /// settings and scene.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn build_context(&mut self) {
+        let ctx = self.new_context(self.window.as_ref().unwrap());
+        let vk_state = self.vk_state.as_ref().unwrap();
         let vertex_buf = Buffer::from_iter(
             ctx.buffer_allocator.clone(),
             BufferCreateInfo {
@@ -154,104 +644,674 @@ impl App {
                     | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
                 ..Default::default()
             },
-            vertexes,
+            self.vertices.iter().copied(),
         )
         .unwrap();
 
-        let command_buffers = get_command_buffers(&vk_state, &ctx, &vertex_buf);
+        let command_buffers = get_command_buffers(vk_state, &ctx, &vertex_buf, self.blend_mode);
 
-        self.window = Some(window);
-        self.vk_state = Some(vk_state);
         self.ctx = Some(ctx);
         self.command_buffers = Some(command_buffers);
         self.vertex_buf = Some(vertex_buf);
     }
 
-    fn render(&self) -> Result<(), DrawingError> {
+    /// Create a context drawing into `window` with the current settings.
+    fn new_context(&self, window: &Arc<Window>) -> DrawingContext {
+        let vk_state = self.vk_state.as_ref().unwrap();
+        let surface = vk_state.create_surface(window.clone());
+        let window_size: [u32; 2] = window.inner_size().into();
+        let mut ctx = DrawingContext::new(vk_state, surface, window_size, self.present_mode);
+        if let Some(image_count) = self.image_count {
+            ctx = ctx.set_image_count(vk_state, window_size, Some(image_count));
+            if ctx.framebuffers.len() as u32 != image_count {
+                log::warn!(
+                    "{} swapchain images were created instead of {image_count}",
+                    ctx.framebuffers.len()
+                );
+            }
+        }
+        if self.resolution != RenderResolution::default() {
+            ctx = ctx.set_resolution(vk_state, window_size, self.resolution);
+        }
+        if self.msaa > 1 {
+            ctx = ctx.set_msaa(vk_state, self.msaa);
+            if ctx.scene.samples as u32 != self.msaa {
+                log::warn!(
+                    "{} samples MSAA isn't supported, using {}",
+                    self.msaa,
+                    ctx.scene.samples as u32
+                );
+            }
+        }
+        if self.depth_test {
+            ctx = ctx.set_depth_test(vk_state, true);
+        }
+        if let Some(clear_color) = self.clear_color {
+            ctx = ctx.set_clear_color(clear_color);
+        }
+        if let Some(path) = &self.lut_path {
+            match Lut::load(path) {
+                Ok(lut) => {
+                    let pass = LutPass::new(vk_state, lut.upload(vk_state, &ctx));
+                    ctx = ctx.edit_post_chain(|chain| chain.push(pass));
+                }
+                Err(e) => log::warn!("Can't load {}: {e}", path.display()),
+            }
+        }
+        if let Some(path) = &self.composite_shader_path {
+            // SAFETY: the shader is supplied by the user running the demo, who
+            // is trusted to pass SPIR-V output by a compiler.
+            match unsafe { CustomPass::load(vk_state, ctx.scene.camera_buf.clone(), path) } {
+                Ok(pass) => ctx = ctx.edit_post_chain(|chain| chain.push(pass)),
+                Err(e) => log::warn!("Can't load {}: {e}", path.display()),
+            }
+        }
+        let text = TextPass::new(vk_state, &ctx, TEXT_SCALE);
+        ctx = ctx.edit_post_chain(|chain| {
+            chain.push(text);
+            chain.set_enabled("text", false);
+        });
+        ctx.scene.set_camera(&self.camera);
+        ctx.scene.set_tonemap(&self.tonemap);
+        ctx.scene.set_bloom(&self.bloom);
+        ctx
+    }
+
+    /// Open or close a second window showing the scene.
+    fn toggle_secondary_view(&mut self, event_loop: &ActiveEventLoop) {
+        if self.secondary.take().is_some() {
+            return;
+        }
+
+        let config = WindowConfig::default()
+            .title("Radiance cascades - secondary view")
+            .size([640, 360]);
+        let window = new_window(event_loop, &config);
+        let ctx = self.new_context(&window);
+        let command_buffers = get_command_buffers(
+            self.vk_state.as_ref().unwrap(),
+            &ctx,
+            self.vertex_buf.as_ref().unwrap(),
+            self.blend_mode,
+        );
+        window.request_redraw();
+        self.secondary = Some(SecondaryView {
+            window,
+            ctx,
+            command_buffers,
+        });
+    }
+
+    fn secondary_window_event(&mut self, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => self.secondary = None,
+            WindowEvent::Resized(_) => self.resize_secondary(),
+            WindowEvent::RedrawRequested => self.render_secondary(),
+            _ => {}
+        }
+    }
+
+    fn render_secondary(&mut self) {
+        let Some(view) = self.secondary.as_ref() else {
+            return;
+        };
+        if !rc_window::is_drawable(&view.window) {
+            return;
+        }
+
+        let vk_state = self.vk_state.as_ref().unwrap();
+        match view
+            .ctx
+            .submit_frame(vk_state, &view.command_buffers, false)
+            .and_then(|frame| frame.wait())
+        {
+            Ok(_) => {}
+            Err(DrawingError::ObsoleteSwapchain) => self.resize_secondary(),
+            Err(DrawingError::SurfaceLost) => {
+                log::warn!("Secondary view surface lost, closing it");
+                self.secondary = None;
+            }
+            Err(e) => {
+                self.schedule_recovery(&e);
+                self.window.as_ref().unwrap().request_redraw();
+            }
+        }
+    }
+
+    /// Recreate the swapchain of the secondary view and record its command
+    /// buffers again.
+    fn resize_secondary(&mut self) {
+        let Some(view) = self.secondary.take() else {
+            return;
+        };
+        // Recreation is postponed until the window is restored.
+        if !rc_window::is_drawable(&view.window) {
+            self.secondary = Some(view);
+            return;
+        }
+
+        let vk_state = self.vk_state.as_ref().unwrap();
+        let vertex_buf = self.vertex_buf.as_ref().unwrap();
+        let ctx = view
+            .ctx
+            .revoke_swapchain(view.window.inner_size().into())
+            .resize_viewport(vk_state, view.window.inner_size().into());
+        let command_buffers = get_command_buffers(vk_state, &ctx, vertex_buf, self.blend_mode);
+
+        view.window.request_redraw();
+        self.secondary = Some(SecondaryView {
+            window: view.window,
+            ctx,
+            command_buffers,
+        });
+    }
+
+    /// Every context drawing the scene, the main one first.
+    fn contexts(&self) -> impl Iterator<Item = &DrawingContext> {
+        self.ctx
+            .iter()
+            .chain(self.secondary.as_ref().map(|view| &view.ctx))
+    }
+
+    /// Redraw every window showing the scene.
+    fn request_redraw(&self) {
+        self.window.as_ref().unwrap().request_redraw();
+        if let Some(view) = &self.secondary {
+            view.window.request_redraw();
+        }
+    }
+
+    /// Tear down the renderer after the device or the surface has been lost,
+    /// and build it again.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn rebuild_renderer(&mut self, event_loop: &ActiveEventLoop) {
+        let device_lost = self.need_rebuild_renderer;
+        self.need_rebuild_renderer = false;
+        self.need_rebuild_surface = false;
+        self.recovery_attempts += 1;
+        if self.recovery_attempts > MAX_RECOVERY_ATTEMPTS {
+            log::error!("Rendering lost {MAX_RECOVERY_ATTEMPTS} times in a row, giving up");
+            event_loop.exit();
+            return;
+        }
+
+        // Everything created from the device has to be dropped before it.
+        self.secondary = None;
+        self.command_buffers = None;
+        self.vertex_buf = None;
+        self.ctx = None;
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            device_lost,
+            attempt = self.recovery_attempts,
+            "Rebuilding the renderer"
+        );
+        if device_lost {
+            log::warn!("Rendering device lost, rebuilding the renderer");
+            self.vk_state = None;
+            let window = self.window.as_ref().unwrap();
+            self.vk_state = Some(vulkan_helper::VulkanState::new(window, &self.gpu));
+        } else {
+            log::warn!("Window surface lost, recreating it");
+        }
+        self.build_context();
+        self.window.as_ref().unwrap().request_redraw();
+    }
+
+    /// Flag what has to be recreated before the next frame can be drawn.
+    fn schedule_recovery(&mut self, e: &DrawingError) {
+        match e {
+            DrawingError::ObsoleteSwapchain => self.need_recreate_swapchain = true,
+            DrawingError::DeviceLost => self.need_rebuild_renderer = true,
+            DrawingError::SurfaceLost => self.need_rebuild_surface = true,
+            DrawingError::CaptureUnsupported => {}
+        }
+    }
+
+    /// Render a frame and read it back before presenting it.
+    fn capture_frame(&mut self) -> Result<RgbaImage, DrawingError> {
         let vk_state = self.vk_state.as_ref().unwrap();
-        let ctx = self.ctx.as_ref().unwrap();
         let command_buffers = self.command_buffers.as_ref().unwrap();
+        self.ctx
+            .as_ref()
+            .unwrap()
+            .capture_frame(vk_state, command_buffers)
+    }
 
-        let (image_idx, suboptimal, acquire_future) =
-            match swapchain::acquire_next_image(ctx.swapchain.clone(), None)
-                .map_err(Validated::unwrap)
-            {
-                Ok(res) => res,
-                Err(VulkanError::OutOfDate) => {
-                    return Err(DrawingError::ObsoleteSwapchain);
-                }
-                Err(e) => panic!("Can't acquire the next image: {e}"),
-            };
-        if suboptimal {
-            return Err(DrawingError::ObsoleteSwapchain);
+    fn save_screenshot(&mut self) {
+        let image = match self.capture_frame() {
+            Ok(image) => image,
+            Err(e) => {
+                self.schedule_recovery(&e);
+                log::warn!("Can't capture the frame: {e}");
+                return;
+            }
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = format!("screenshot-{timestamp}.png");
+        match image.save(&path) {
+            Ok(()) => println!("Screenshot saved to {path}"),
+            Err(e) => log::warn!("Can't save screenshot to {path}: {e}"),
         }
+    }
 
-        let exec = sync::now(vk_state.device.clone())
-            .join(acquire_future)
-            .then_execute(
-                vk_state.queue.clone(),
-                command_buffers[image_idx as usize].clone(),
-            )
-            .map(|fut| {
-                fut.then_swapchain_present(
-                    vk_state.queue.clone(),
-                    SwapchainPresentInfo::swapchain_image_index(ctx.swapchain.clone(), image_idx),
-                )
+    fn bench_frame(&mut self, event_loop: &ActiveEventLoop) {
+        if let Err(e) = self.render() {
+            self.schedule_recovery(&e);
+            self.window.as_ref().unwrap().request_redraw();
+            return;
+        }
+
+        let bench = self.bench.as_mut().unwrap();
+        bench.samples.extend(self.stats.last().cloned());
+        if bench.samples.len() < bench.frame_count as usize {
+            self.window.as_ref().unwrap().request_redraw();
+            return;
+        }
+
+        let samples = &bench.samples;
+        println!("{} frames, {BENCH_TRIANGLES} triangles", samples.len());
+        let report = |name: &str, times: Vec<Duration>| {
+            if let Some(summary) = TimingSummary::new(times) {
+                println!("{name:>12}: {summary}");
+            }
+        };
+        report(
+            "frame",
+            samples
+                .iter()
+                .map(|sample| sample.cpu_time + sample.gpu_wait_time)
+                .collect(),
+        );
+        report(
+            "cpu",
+            samples.iter().map(|sample| sample.cpu_time).collect(),
+        );
+        report(
+            "gpu wait",
+            samples.iter().map(|sample| sample.gpu_wait_time).collect(),
+        );
+        for pass in self.ctx.as_ref().unwrap().timed_passes() {
+            report(
+                pass,
+                samples
+                    .iter()
+                    .flat_map(|sample| sample.gpu_passes.iter())
+                    .filter(|timing| timing.name == pass)
+                    .map(|timing| timing.time)
+                    .collect(),
+            );
+        }
+
+        event_loop.exit();
+    }
+
+    fn record_frame(&mut self, event_loop: &ActiveEventLoop) {
+        let image = match self.capture_frame() {
+            Ok(image) => image,
+            Err(DrawingError::CaptureUnsupported) => {
+                panic!("Can't record frame: {}", DrawingError::CaptureUnsupported)
+            }
+            Err(e) => {
+                self.schedule_recovery(&e);
+                self.window.as_ref().unwrap().request_redraw();
+                return;
+            }
+        };
+
+        let recording = self.recording.as_mut().unwrap();
+        let path = recording
+            .dir
+            .join(format!("frame_{:05}.png", recording.recorded));
+        image
+            .save(&path)
+            .unwrap_or_else(|e| panic!("Can't save {}: {e}", path.display()));
+        recording.recorded += 1;
+
+        if recording.recorded == recording.frame_count {
+            println!(
+                "Recorded {} frames into {}",
+                recording.recorded,
+                recording.dir.display()
+            );
+            event_loop.exit();
+        } else {
+            // Recorded frames are exactly one simulation step apart.
+            self.step_simulation();
+            self.window.as_ref().unwrap().request_redraw();
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn render(&mut self) -> Result<(), DrawingError> {
+        let frame_start = Instant::now();
+        let vk_state = self.vk_state.as_ref().unwrap();
+        let ctx = self.ctx.as_ref().unwrap();
+        let command_buffers = self.command_buffers.as_ref().unwrap();
+
+        let frame = ctx.submit_frame(vk_state, command_buffers, false)?;
+        let image_idx = frame.image_idx;
+
+        let wait_start = Instant::now();
+        frame.wait()?;
+        let wait_end = Instant::now();
+
+        let pass_names = ctx.timed_passes();
+        let gpu_passes = ctx
+            .gpu_timer
+            .as_ref()
+            .and_then(|timer| timer.read(image_idx, pass_names.len() as u32))
+            .map(|times| {
+                pass_names
+                    .into_iter()
+                    .zip(times)
+                    .map(|(name, time)| PassTiming { name, time })
+                    .collect()
             })
-            .map(|fut| fut.then_signal_fence_and_flush())
-            .unwrap();
+            .unwrap_or_default();
 
-        exec.and_then(|exec| exec.wait(None))
-            .expect("Rendering failed");
+        self.stats.record(FrameSample {
+            cpu_time: wait_start - frame_start,
+            gpu_wait_time: wait_end - wait_start,
+            draw_count: 1,
+            vertex_count: self.vertex_buf.as_ref().unwrap().len() as u32,
+            gpu_passes,
+        });
 
         Ok(())
     }
 
-    fn handle_input(&mut self, event: KeyEvent) {
-        let is_space: bool = event.physical_key == PhysicalKey::Code(KeyCode::Space);
-        let is_pressed: bool = event.state.is_pressed();
-        if !is_space || !is_pressed {
+    /// Run the steps elapsed since the previous frame, then draw the camera
+    /// between its last two states.
+    fn advance_simulation(&mut self) {
+        let Some(simulation) = self.simulation.as_mut() else {
+            return;
+        };
+
+        let now = Instant::now();
+        if simulation.paused {
+            simulation.last_tick = now;
             return;
         }
+        simulation.accumulator += now - simulation.last_tick;
+        simulation.last_tick = now;
+        let mut steps = 0;
+        while simulation.accumulator >= UPDATE_STEP {
+            if steps == MAX_UPDATES_PER_FRAME {
+                simulation.accumulator = Duration::ZERO;
+                break;
+            }
+            simulation.step(&mut self.camera);
+            simulation.accumulator -= UPDATE_STEP;
+            steps += 1;
+        }
 
-        let vk_state = self.vk_state.as_ref().unwrap();
+        let alpha = simulation.accumulator.as_secs_f32() / UPDATE_STEP.as_secs_f32();
+        let camera = simulation.previous_camera.lerp(&self.camera, alpha);
+        self.contexts()
+            .for_each(|ctx| ctx.scene.set_camera(&camera));
+    }
+
+    fn toggle_spin(&mut self) {
+        match self.simulation.as_mut() {
+            Some(simulation) => simulation.spinning = !simulation.spinning,
+            None => log::warn!("Spinning the camera requires --continuous"),
+        }
+    }
+
+    /// Freeze the simulation, or resume it without catching up on the time
+    /// spent paused.
+    fn toggle_pause(&mut self) {
+        let Some(simulation) = self.simulation.as_mut() else {
+            log::warn!("Pausing requires --continuous");
+            return;
+        };
+        simulation.paused = !simulation.paused;
+        simulation.accumulator = Duration::ZERO;
+        self.contexts()
+            .for_each(|ctx| ctx.scene.set_camera(&self.camera));
+        self.request_redraw();
+    }
+
+    /// Advance a paused simulation by exactly one update and draw it.
+    fn step_frame(&mut self) {
+        if self.simulation.as_ref().is_some_and(|s| s.paused) {
+            self.step_simulation();
+            self.request_redraw();
+        }
+    }
+
+    /// Run a single update, ignoring the elapsed time, and draw its result
+    /// without interpolation.
+    fn step_simulation(&mut self) {
+        let Some(simulation) = self.simulation.as_mut() else {
+            return;
+        };
+        simulation.step(&mut self.camera);
+        self.contexts()
+            .for_each(|ctx| ctx.scene.set_camera(&self.camera));
+    }
+
+    fn handle_input(&mut self, event_loop: &ActiveEventLoop, event: KeyEvent) {
+        let Some(action) = self.key_map.pressed_action(&event).cloned() else {
+            return;
+        };
+
+        match action {
+            Action::Quit => event_loop.exit(),
+            Action::ToggleSecondaryView => self.toggle_secondary_view(event_loop),
+            Action::ToggleStats => self.toggle_stats_overlay(),
+            Action::ToggleFullscreen => rc_window::toggle_fullscreen(self.window.as_ref().unwrap()),
+            Action::Screenshot => self.save_screenshot(),
+            Action::ScaleExposure(factor) => {
+                self.update_tonemap(|tonemap| tonemap.exposure *= factor)
+            }
+            Action::CycleTonemap => {
+                self.update_tonemap(|tonemap| tonemap.operator = tonemap.operator.next())
+            }
+            Action::ScaleBloom(factor) => self.update_bloom(|bloom| bloom.strength *= factor),
+            Action::TogglePostPass(name) => self.toggle_post_pass(name),
+            Action::ToggleUpscaleFilter => self.toggle_upscale_filter(),
+            Action::CycleBlendMode => self.cycle_blend_mode(),
+            Action::CyclePresentMode => self.cycle_present_mode(),
+            Action::ToggleSpin => self.toggle_spin(),
+            Action::TogglePause => self.toggle_pause(),
+            Action::StepFrame => self.step_frame(),
+            Action::RandomizeTriangle => self.randomize_triangle(),
+            Action::Move(delta) => self.move_selection(delta),
+        }
+    }
+
+    /// Show or hide the on-screen statistics readout.
+    fn toggle_stats_overlay(&mut self) {
+        self.toggle_post_pass("text");
+        // Filled before the redraw, instead of after it, to show up at once.
+        self.update_overlay();
+    }
+
+    /// Show the frame statistics in the text pass, when it is enabled. The
+    /// frame has been waited for, so the text buffer is free.
+    fn update_overlay(&self) {
         let ctx = self.ctx.as_ref().unwrap();
-        let command_allocator = &ctx.command_buffer_allocator;
+        if !ctx.scene.post_chain.is_enabled("text") {
+            return;
+        }
+        if let Some(pass) = ctx.scene.post_chain.get::<TextPass>() {
+            pass.set_text(&self.stats.to_string().replace(", ", "\n"));
+        }
+    }
 
-        let rng = RefCell::new(thread_rng());
-        let gen_coords = || {
-            let mut rng = rng.borrow_mut();
-            [rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)]
+    /// Append the frame statistics to the window title, at most every
+    /// `TITLE_UPDATE_INTERVAL` so it stays readable.
+    fn update_title(&mut self) {
+        let Some(title_stats) = self.title_stats.as_mut() else {
+            return;
         };
-        let gen_color = || {
-            let mut rng = rng.borrow_mut();
-            [rng.gen(), rng.gen(), rng.gen()]
+        if title_stats.last_update.elapsed() < TITLE_UPDATE_INTERVAL {
+            return;
+        }
+        title_stats.last_update = Instant::now();
+
+        let Some(fps) = self.stats.average_fps() else {
+            return;
         };
-        let vertexes = [
-            Vertex2DBuilder::new(gen_coords())
-                .color(gen_color())
-                .build(),
-            Vertex2DBuilder::new(gen_coords())
-                .color(gen_color())
-                .build(),
-            Vertex2DBuilder::new(gen_coords())
-                .color(gen_color())
-                .build(),
-        ];
-        let vertex_buf = Buffer::from_iter(
-            ctx.buffer_allocator.clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::TRANSFER_SRC,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_HOST
-                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            vertexes,
-        )
-        .unwrap();
+        self.window.as_ref().unwrap().set_title(&format!(
+            "{} - {fps:.0} fps ({:.2?})",
+            title_stats.title,
+            self.stats.average_frame_time()
+        ));
+    }
+
+    fn update_tonemap(&mut self, update: impl FnOnce(&mut Tonemap)) {
+        update(&mut self.tonemap);
+        self.contexts()
+            .for_each(|ctx| ctx.scene.set_tonemap(&self.tonemap));
+        self.request_redraw();
+    }
+
+    fn update_bloom(&mut self, update: impl FnOnce(&mut Bloom)) {
+        update(&mut self.bloom);
+        self.contexts()
+            .for_each(|ctx| ctx.scene.set_bloom(&self.bloom));
+        self.request_redraw();
+    }
+
+    fn toggle_post_pass(&mut self, name: &str) {
+        if self
+            .ctx
+            .as_ref()
+            .unwrap()
+            .scene
+            .post_chain
+            .position(name)
+            .is_none()
+        {
+            return;
+        }
+        let vk_state = self.vk_state.as_ref().unwrap();
+        let vertex_buf = self.vertex_buf.as_ref().unwrap();
+        let mut ctx = self.ctx.take().unwrap();
+        ctx = ctx.edit_post_chain(|chain| {
+            chain.set_enabled(name, !chain.is_enabled(name));
+        });
+
+        self.command_buffers = Some(get_command_buffers(
+            vk_state,
+            &ctx,
+            vertex_buf,
+            self.blend_mode,
+        ));
+        self.ctx = Some(ctx);
+        self.window.as_ref().unwrap().request_redraw();
+    }
+
+    fn cycle_present_mode(&mut self) {
+        let window = self.window.as_ref().unwrap();
+        if !rc_window::is_drawable(window) {
+            return;
+        }
+        let vk_state = self.vk_state.as_ref().unwrap();
+        let vertex_buf = self.vertex_buf.as_ref().unwrap();
+        self.present_mode = self.present_mode.next();
+
+        let mut ctx = self.ctx.take().unwrap();
+        ctx = ctx.set_present_mode(vk_state, window.inner_size().into(), self.present_mode);
+        log::info!(
+            "Present mode: {:?}, using {:?}",
+            self.present_mode,
+            ctx.swapchain.present_mode()
+        );
+
+        self.command_buffers = Some(get_command_buffers(
+            vk_state,
+            &ctx,
+            vertex_buf,
+            self.blend_mode,
+        ));
+        self.ctx = Some(ctx);
+        window.request_redraw();
+    }
+
+    fn cycle_blend_mode(&mut self) {
+        self.blend_mode = self.blend_mode.next();
+        log::info!("Blend mode: {:?}", self.blend_mode);
+
+        let vk_state = self.vk_state.as_ref().unwrap();
+        let vertex_buf = self.vertex_buf.as_ref().unwrap();
+        let ctx = self.ctx.as_ref().unwrap();
+        self.command_buffers = Some(get_command_buffers(
+            vk_state,
+            ctx,
+            vertex_buf,
+            self.blend_mode,
+        ));
+        if let Some(view) = self.secondary.as_mut() {
+            view.command_buffers =
+                get_command_buffers(vk_state, &view.ctx, vertex_buf, self.blend_mode);
+        }
+        self.request_redraw();
+    }
+
+    fn toggle_upscale_filter(&mut self) {
+        let vk_state = self.vk_state.as_ref().unwrap();
+        let vertex_buf = self.vertex_buf.as_ref().unwrap();
+        let mut ctx = self.ctx.take().unwrap();
+        let filter = match ctx.upscale_filter {
+            Filter::Nearest => Filter::Linear,
+            _ => Filter::Nearest,
+        };
+        ctx = ctx.set_upscale_filter(vk_state, filter);
+
+        self.command_buffers = Some(get_command_buffers(
+            vk_state,
+            &ctx,
+            vertex_buf,
+            self.blend_mode,
+        ));
+        self.ctx = Some(ctx);
+        self.window.as_ref().unwrap().request_redraw();
+    }
+
+    /// Update both the CPU copy and the vertex buffer.
+    fn set_vertex_position(&mut self, idx: usize, position: [f32; 2]) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(idx, ?position, "Vertex moved");
+        self.vertices[idx].set_position(position);
+        let vertex_buf = self.vertex_buf.as_ref().unwrap();
+        vertex_buf.write().unwrap()[idx].set_position(position);
+    }
+
+    /// Move the selected vertex, or the camera when nothing is selected.
+    fn move_selection(&mut self, delta: [f32; 2]) {
+        let delta = delta.map(|x| x / self.camera.zoom);
+
+        if let Some(idx) = self.selected_vertex {
+            let [x, y] = self.vertices[idx].position();
+            self.set_vertex_position(idx, [x + delta[0], y + delta[1]]);
+        } else {
+            self.camera.pan(delta);
+            self.contexts()
+                .for_each(|ctx| ctx.scene.set_camera(&self.camera));
+        }
+
+        self.request_redraw();
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn randomize_triangle(&mut self) {
+        let vk_state = self.vk_state.as_ref().unwrap();
+        let ctx = self.ctx.as_ref().unwrap();
+        let command_allocator = &ctx.command_buffer_allocator;
+
+        let vertexes = random_triangles(1);
+        let vertex_buf = ctx
+            .transient_allocator
+            .allocate_slice::<Vertex2D>(vertexes.len() as u64)
+            .unwrap();
+        vertex_buf.write().unwrap().copy_from_slice(&vertexes);
 
         let cmd = AutoCommandBufferBuilder::primary(
             command_allocator,
@@ -275,32 +1335,89 @@ impl App {
             .then_signal_fence_and_flush()
             .and_then(|fut| fut.wait(None))
             .unwrap();
+        let count = vertexes.len().min(self.vertices.len());
+        self.vertices[..count].copy_from_slice(&vertexes[..count]);
 
-        self.render().unwrap();
+        if let Err(e) = self.render() {
+            self.schedule_recovery(&e);
+        }
+        if let Some(view) = &self.secondary {
+            view.window.request_redraw();
+        }
     }
 
-    fn recreate_swapchain(&mut self) {
-        self.need_recreate_swapchain = false;
+    fn cursor_to_world(&self, position: PhysicalPosition<f64>) -> [f32; 2] {
+        let ctx = self.ctx.as_ref().unwrap();
+        let extent = ctx.swapchain.image_extent().map(|x| x as f32);
+        self.camera.screen_to_world(
+            &ctx.scene.projection,
+            extent,
+            [position.x as f32, position.y as f32],
+        )
+    }
 
-        let window = self.window.as_ref().unwrap();
+    fn handle_cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        let previous = self.cursor_position.replace(position);
 
-        let mut ctx = self.ctx.take().unwrap();
-        ctx = ctx.revoke_swapchain(window);
-        self.ctx = Some(ctx);
+        if let Some(idx) = self.selected_vertex.filter(|_| self.dragging) {
+            let world = self.cursor_to_world(position);
+            self.set_vertex_position(idx, world);
+            self.request_redraw();
+        } else if let Some(previous) = previous.filter(|_| self.panning) {
+            let from = self.cursor_to_world(previous);
+            let to = self.cursor_to_world(position);
+            self.camera.pan([from[0] - to[0], from[1] - to[1]]);
+            self.contexts()
+                .for_each(|ctx| ctx.scene.set_camera(&self.camera));
+            self.request_redraw();
+        }
+    }
+
+    fn handle_mouse_input(&mut self, state: ElementState, button: MouseButton) {
+        match (button, state) {
+            (MouseButton::Left, ElementState::Pressed) => {
+                let Some(position) = self.cursor_position else {
+                    return;
+                };
+                let world = self.cursor_to_world(position);
+                let radius = PICK_RADIUS / self.camera.zoom;
+                self.selected_vertex = geometry::pick_vertex(&self.vertices, world, radius);
+                self.dragging = self.selected_vertex.is_some();
+            }
+            (MouseButton::Left, ElementState::Released) => self.dragging = false,
+            (MouseButton::Right, state) => self.panning = state.is_pressed(),
+            _ => {}
+        }
     }
 
-    fn resize_viewport(&mut self) {
+    fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        let steps = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / 32.0,
+        };
+        self.camera.zoom_by(ZOOM_STEP.powf(steps));
+        self.contexts()
+            .for_each(|ctx| ctx.scene.set_camera(&self.camera));
+        self.request_redraw();
+    }
+
+    /// Recreate the swapchain for the current window size, e.g. after a
+    /// resize or when it became obsolete, and record the command buffers
+    /// targeting it.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn recreate_swapchain(&mut self) {
         self.window_resized = false;
+        self.need_recreate_swapchain = false;
 
         let window = self.window.as_ref().unwrap();
         let vk_state = self.vk_state.as_ref().unwrap();
         let vertex_buf = self.vertex_buf.as_ref().unwrap();
 
         let mut ctx = self.ctx.take().unwrap();
-        ctx = ctx.revoke_swapchain(window);
-        ctx = ctx.resize_viewport(vk_state, window);
+        ctx = ctx.revoke_swapchain(window.inner_size().into());
+        ctx = ctx.resize_viewport(vk_state, window.inner_size().into());
 
-        let new_command_buffers = get_command_buffers(vk_state, &ctx, vertex_buf);
+        let new_command_buffers = get_command_buffers(vk_state, &ctx, vertex_buf, self.blend_mode);
 
         self.ctx = Some(ctx);
         self.command_buffers = Some(new_command_buffers);
@@ -308,8 +1425,73 @@ impl App {
 }
 
 fn main() {
+    // The demo reports its own state changes, dependencies only their warnings.
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("warn,radiance_cascades=info"),
+    )
+    .init();
+
+    let Options {
+        recording,
+        bench,
+        lut,
+        composite_shader,
+        resolution,
+        msaa,
+        depth_test,
+        clear_color,
+        present_mode,
+        image_count,
+        gpu,
+        list_gpus,
+        continuous,
+        title,
+        title_stats,
+    } = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}\n{USAGE}");
+        std::process::exit(2);
+    });
+    if list_gpus {
+        for adapter in vulkan_helper::enumerate_adapters() {
+            println!(
+                "{}: {} ({:?})",
+                adapter.index, adapter.name, adapter.device_type
+            );
+        }
+        return;
+    }
+    if let Some(recording) = &recording {
+        std::fs::create_dir_all(&recording.dir).expect("Can't create the output directory");
+    }
+
+    let title = title.unwrap_or_else(|| "Radiance cascades".to_owned());
+    // Recordings step the simulation once per frame instead of following
+    // the wall clock.
+    let simulated = continuous || recording.is_some();
     let event_loop = EventLoop::new().unwrap();
-    let mut app = App::default();
+    let mut app = App {
+        window_config: WindowConfig::default()
+            .title(title.clone())
+            .size([1280, 720]),
+        key_map: default_key_map(),
+        recording,
+        bench,
+        lut_path: lut,
+        composite_shader_path: composite_shader,
+        resolution,
+        msaa,
+        depth_test,
+        clear_color,
+        present_mode,
+        image_count,
+        gpu,
+        simulation: simulated.then(|| Simulation::new(Camera2D::default())),
+        title_stats: title_stats.then(|| TitleStats {
+            title,
+            last_update: Instant::now(),
+        }),
+        ..Default::default()
+    };
     event_loop.set_control_flow(ControlFlow::Wait);
     event_loop.run_app(&mut app).unwrap();
 }
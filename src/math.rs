@@ -0,0 +1,295 @@
+type Point = [f32; 2];
+
+/// Axis aligned rectangle, `position` being its bottom left corner in world
+/// units, where the Y axis points up.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rect {
+    pub position: Point,
+    pub size: [f32; 2],
+}
+impl Rect {
+    pub fn new(position: Point, size: [f32; 2]) -> Self {
+        Self { position, size }
+    }
+
+    pub fn from_center(center: Point, size: [f32; 2]) -> Self {
+        Self {
+            position: [center[0] - size[0] / 2.0, center[1] - size[1] / 2.0],
+            size,
+        }
+    }
+
+    /// Smallest rectangle containing every point, `None` when there is none.
+    pub fn bounding(points: impl IntoIterator<Item = Point>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), point| {
+            (
+                [min[0].min(point[0]), min[1].min(point[1])],
+                [max[0].max(point[0]), max[1].max(point[1])],
+            )
+        });
+
+        Some(Self {
+            position: min,
+            size: [max[0] - min[0], max[1] - min[1]],
+        })
+    }
+
+    pub fn min(&self) -> Point {
+        self.position
+    }
+
+    pub fn max(&self) -> Point {
+        [
+            self.position[0] + self.size[0],
+            self.position[1] + self.size[1],
+        ]
+    }
+
+    pub fn center(&self) -> Point {
+        [
+            self.position[0] + self.size[0] / 2.0,
+            self.position[1] + self.size[1] / 2.0,
+        ]
+    }
+
+    /// Corners in counter-clockwise order, starting from `position`.
+    pub fn corners(&self) -> [Point; 4] {
+        let [x0, y0] = self.min();
+        let [x1, y1] = self.max();
+        [[x0, y0], [x1, y0], [x1, y1], [x0, y1]]
+    }
+
+    /// Whether `point` lies inside, edges included.
+    pub fn contains(&self, point: Point) -> bool {
+        let [x0, y0] = self.min();
+        let [x1, y1] = self.max();
+        (x0..=x1).contains(&point[0]) && (y0..=y1).contains(&point[1])
+    }
+
+    /// Whether both rectangles overlap, touching edges included.
+    pub fn intersects(&self, other: &Rect) -> bool {
+        let [min, max] = [self.min(), self.max()];
+        let [other_min, other_max] = [other.min(), other.max()];
+        min[0] <= other_max[0]
+            && other_min[0] <= max[0]
+            && min[1] <= other_max[1]
+            && other_min[1] <= max[1]
+    }
+
+    pub fn translate(self, delta: Point) -> Self {
+        Self {
+            position: [self.position[0] + delta[0], self.position[1] + delta[1]],
+            ..self
+        }
+    }
+
+    /// Grow by `margin` on every side, shrinking when negative.
+    pub fn expand(self, margin: f32) -> Self {
+        Self {
+            position: [self.position[0] - margin, self.position[1] - margin],
+            size: [self.size[0] + 2.0 * margin, self.size[1] + 2.0 * margin],
+        }
+    }
+}
+
+/// Column-major 3x3 matrix, laid out as GLSL expects a `mat3`.
+pub type Mat3 = [[f32; 3]; 3];
+/// Column-major 4x4 matrix, laid out as GLSL expects a `mat4`.
+pub type Mat4 = [[f32; 4]; 4];
+
+/// Affine 2D transform, applying `linear` then `translation`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform2D {
+    /// Rotation, scale and shear, column-major.
+    pub linear: [[f32; 2]; 2],
+    pub translation: Point,
+}
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+impl Transform2D {
+    pub const IDENTITY: Self = Self {
+        linear: [[1.0, 0.0], [0.0, 1.0]],
+        translation: [0.0, 0.0],
+    };
+
+    pub fn from_translation(translation: Point) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Counter-clockwise rotation, in radians.
+    pub fn from_rotation(angle: f32) -> Self {
+        let (s, c) = angle.sin_cos();
+        Self {
+            linear: [[c, s], [-s, c]],
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn from_scale(scale: [f32; 2]) -> Self {
+        Self {
+            linear: [[scale[0], 0.0], [0.0, scale[1]]],
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Transform applying `self`, then `next`.
+    pub fn then(&self, next: &Transform2D) -> Self {
+        Self {
+            linear: self.linear.map(|column| next.apply_vector(column)),
+            translation: next.apply_point(self.translation),
+        }
+    }
+
+    /// `None` when the transform collapses the plane, e.g. a zero scale.
+    pub fn inverse(&self) -> Option<Self> {
+        let [[a, b], [c, d]] = self.linear;
+        let det = a * d - b * c;
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+
+        let linear = [[d / det, -b / det], [-c / det, a / det]];
+        let inverse = Self {
+            linear,
+            translation: [0.0, 0.0],
+        };
+        let [x, y] = inverse.apply_vector(self.translation);
+        Some(Self {
+            translation: [-x, -y],
+            ..inverse
+        })
+    }
+
+    pub fn apply_point(&self, point: Point) -> Point {
+        let [x, y] = self.apply_vector(point);
+        [x + self.translation[0], y + self.translation[1]]
+    }
+
+    /// Apply the linear part only, e.g. to a direction or an offset.
+    pub fn apply_vector(&self, vector: [f32; 2]) -> [f32; 2] {
+        let [[a, b], [c, d]] = self.linear;
+        [a * vector[0] + c * vector[1], b * vector[0] + d * vector[1]]
+    }
+
+    pub fn to_mat3(&self) -> Mat3 {
+        let [[a, b], [c, d]] = self.linear;
+        let [tx, ty] = self.translation;
+        [[a, b, 0.0], [c, d, 0.0], [tx, ty, 1.0]]
+    }
+
+    /// Embedding in the XY plane, leaving Z untouched.
+    pub fn to_mat4(&self) -> Mat4 {
+        let [[a, b], [c, d]] = self.linear;
+        let [tx, ty] = self.translation;
+        [
+            [a, b, 0.0, 0.0],
+            [c, d, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [tx, ty, 0.0, 1.0],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_center_and_bounds() {
+        let rect = Rect::from_center([1.0, 2.0], [4.0, 2.0]);
+        assert_eq!(rect.min(), [-1.0, 1.0]);
+        assert_eq!(rect.max(), [3.0, 3.0]);
+        assert_eq!(rect.center(), [1.0, 2.0]);
+        assert_eq!(
+            rect.corners(),
+            [[-1.0, 1.0], [3.0, 1.0], [3.0, 3.0], [-1.0, 3.0]]
+        );
+    }
+
+    #[test]
+    fn bounding() {
+        assert_eq!(Rect::bounding([]), None);
+        assert_eq!(
+            Rect::bounding([[1.0, 1.0]]),
+            Some(Rect::new([1.0, 1.0], [0.0, 0.0]))
+        );
+        assert_eq!(
+            Rect::bounding([[1.0, -1.0], [-2.0, 3.0], [0.0, 0.0]]),
+            Some(Rect::new([-2.0, -1.0], [3.0, 4.0]))
+        );
+    }
+
+    #[test]
+    fn contains_includes_edges() {
+        let rect = Rect::new([0.0, 0.0], [2.0, 1.0]);
+        assert!(rect.contains([0.0, 0.0]));
+        assert!(rect.contains([2.0, 1.0]));
+        assert!(rect.contains([1.0, 1.0]));
+        assert!(!rect.contains([2.1, 0.5]));
+        assert!(!rect.contains([1.0, -0.1]));
+    }
+
+    #[test]
+    fn intersects_includes_touching_edges() {
+        let rect = Rect::new([0.0, 0.0], [1.0, 1.0]);
+        assert!(rect.intersects(&Rect::new([0.5, 0.5], [1.0, 1.0])));
+        assert!(rect.intersects(&Rect::new([1.0, 0.0], [1.0, 1.0])));
+        assert!(rect.intersects(&Rect::new([1.0, 1.0], [1.0, 1.0])));
+        assert!(rect.intersects(&Rect::new([0.25, 0.25], [0.5, 0.5])));
+        assert!(!rect.intersects(&Rect::new([1.5, 0.0], [1.0, 1.0])));
+        assert!(!rect.intersects(&Rect::new([0.0, -2.0], [1.0, 1.0])));
+    }
+
+    #[test]
+    fn translate_and_expand() {
+        let rect = Rect::new([0.0, 0.0], [2.0, 2.0]).translate([1.0, -1.0]);
+        assert_eq!(rect, Rect::new([1.0, -1.0], [2.0, 2.0]));
+        assert_eq!(rect.expand(0.5), Rect::new([0.5, -1.5], [3.0, 3.0]));
+        assert_eq!(rect.expand(-1.0), Rect::new([2.0, 0.0], [0.0, 0.0]));
+    }
+
+    fn assert_close(a: Point, b: Point) {
+        assert!(
+            (a[0] - b[0]).abs() < 1e-5 && (a[1] - b[1]).abs() < 1e-5,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn then_applies_in_order() {
+        let transform = Transform2D::from_translation([1.0, 0.0])
+            .then(&Transform2D::from_rotation(std::f32::consts::FRAC_PI_2))
+            .then(&Transform2D::from_scale([2.0, 2.0]));
+        // (1, 1) -> (2, 1) -> (-1, 2) -> (-2, 4)
+        assert_close(transform.apply_point([1.0, 1.0]), [-2.0, 4.0]);
+        // Translation doesn't apply to vectors.
+        assert_close(transform.apply_vector([1.0, 0.0]), [0.0, 2.0]);
+    }
+
+    #[test]
+    fn inverse_round_trips() {
+        let transform = Transform2D::from_scale([2.0, 0.5])
+            .then(&Transform2D::from_rotation(0.3))
+            .then(&Transform2D::from_translation([3.0, -1.0]));
+        let inverse = transform.inverse().unwrap();
+        for point in [[0.0, 0.0], [1.0, 2.0], [-4.0, 0.5]] {
+            assert_close(inverse.apply_point(transform.apply_point(point)), point);
+            assert_close(transform.then(&inverse).apply_point(point), point);
+        }
+    }
+
+    #[test]
+    fn inverse_of_zero_scale_is_none() {
+        assert_eq!(Transform2D::from_scale([0.0, 1.0]).inverse(), None);
+        assert_eq!(Transform2D::from_scale([0.0, 0.0]).inverse(), None);
+        assert_eq!(Transform2D::IDENTITY.inverse(), Some(Transform2D::IDENTITY));
+    }
+}
@@ -0,0 +1,34 @@
+#version 460
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+layout(set = 0, binding = 1) uniform Params {
+    float threshold;
+    float strength;
+} params;
+
+layout(push_constant) uniform Pass {
+    vec2 texel_size;
+    // Set on the first downsample, reading the scene.
+    uint prefilter;
+} pass;
+
+void main() {
+    vec4 offset = pass.texel_size.xyxy * vec4(-1.0, -1.0, 1.0, 1.0);
+    vec3 color = 0.25 * (
+        texture(source, uv + offset.xy).rgb +
+        texture(source, uv + offset.zy).rgb +
+        texture(source, uv + offset.xw).rgb +
+        texture(source, uv + offset.zw).rgb
+    );
+
+    if (pass.prefilter != 0u) {
+        float brightness = max(color.r, max(color.g, color.b));
+        color *= max(brightness - params.threshold, 0.0) / max(brightness, 1e-5);
+    }
+
+    f_color = vec4(color, 1.0);
+}
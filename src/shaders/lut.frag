@@ -0,0 +1,32 @@
+#version 460
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+layout(set = 0, binding = 1) uniform sampler3D lut;
+
+vec3 linear_to_srgb(vec3 color) {
+    vec3 higher = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    vec3 lower = color * 12.92;
+    return mix(higher, lower, lessThan(color, vec3(0.0031308)));
+}
+
+vec3 srgb_to_linear(vec3 color) {
+    vec3 higher = pow((color + 0.055) / 1.055, vec3(2.4));
+    vec3 lower = color / 12.92;
+    return mix(higher, lower, lessThan(color, vec3(0.04045)));
+}
+
+void main() {
+    // Grading LUTs are authored against display encoded colors.
+    vec3 color = linear_to_srgb(clamp(texture(source, uv).rgb, 0.0, 1.0));
+
+    // Sample the centers of the outer texels for the domain bounds.
+    float size = float(textureSize(lut, 0).x);
+    vec3 coords = color * ((size - 1.0) / size) + 0.5 / size;
+    color = texture(lut, coords).rgb;
+
+    f_color = vec4(srgb_to_linear(color), 1.0);
+}
@@ -0,0 +1,26 @@
+#version 460
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Present {
+    // Set when the target doesn't encode to sRGB by itself.
+    uint encode_srgb;
+} present;
+
+vec3 linear_to_srgb(vec3 color) {
+    vec3 higher = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    vec3 lower = color * 12.92;
+    return mix(higher, lower, lessThan(color, vec3(0.0031308)));
+}
+
+void main() {
+    vec3 color = clamp(texture(source, uv).rgb, 0.0, 1.0);
+    if (present.encode_srgb != 0u) {
+        color = linear_to_srgb(color);
+    }
+    f_color = vec4(color, 1.0);
+}
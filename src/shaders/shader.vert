@@ -5,7 +5,17 @@ layout(location = 1) in vec3 color;
 
 layout(location = 0) out vec3 fragColor;
 
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+} camera;
+
+// Only used by the depth test, see `context::layer_depth`.
+layout(push_constant) uniform Layer {
+    float depth;
+} layer;
+
 void main() {
-    gl_Position = vec4(position, 0.0, 1.0);
+    gl_Position = camera.proj * camera.view * vec4(position, layer.depth, 1.0);
     fragColor = color;
 }
@@ -0,0 +1,14 @@
+#version 460
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D atlas;
+
+void main() {
+    if (texture(atlas, uv).r < 0.5) {
+        discard;
+    }
+    f_color = vec4(1.0);
+}
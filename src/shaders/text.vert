@@ -0,0 +1,25 @@
+#version 460
+
+layout(location = 0) in vec2 origin;
+layout(location = 1) in uint glyph;
+
+layout(location = 0) out vec2 uv;
+
+layout(push_constant) uniform Params {
+    vec2 target_size;
+    // Size of a glyph cell in the atlas, in texels, then the atlas size.
+    vec2 cell_size;
+    vec2 atlas_size;
+    float scale;
+} params;
+
+void main() {
+    // Quad drawn as a 4 vertices strip.
+    vec2 corner = vec2(gl_VertexIndex & 1, (gl_VertexIndex >> 1) & 1);
+    // The last column of a cell is spacing.
+    vec2 glyph_size = params.cell_size - vec2(1.0, 0.0);
+    vec2 pixel = origin + corner * glyph_size * params.scale;
+
+    gl_Position = vec4(pixel / params.target_size * 2.0 - 1.0, 0.0, 1.0);
+    uv = (vec2(float(glyph) * params.cell_size.x, 0.0) + corner * glyph_size) / params.atlas_size;
+}
@@ -0,0 +1,46 @@
+#version 460
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D hdr;
+layout(set = 0, binding = 1) uniform Params {
+    float exposure;
+    uint mode;
+} params;
+
+const uint MODE_CLAMP = 0u;
+const uint MODE_REINHARD = 1u;
+const uint MODE_ACES = 2u;
+
+vec3 reinhard(vec3 color) {
+    return color / (1.0 + color);
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve.
+vec3 aces(vec3 color) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return (color * (a * color + b)) / (color * (c * color + d) + e);
+}
+
+void main() {
+    vec3 color = texture(hdr, uv).rgb * params.exposure;
+
+    switch (params.mode) {
+        case MODE_REINHARD:
+            color = reinhard(color);
+            break;
+        case MODE_ACES:
+            color = aces(color);
+            break;
+        default:
+            break;
+    }
+
+    f_color = vec4(clamp(color, 0.0, 1.0), 1.0);
+}
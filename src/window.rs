@@ -0,0 +1,79 @@
+use winit::{
+    dpi::PhysicalSize,
+    window::{Fullscreen, Window, WindowAttributes},
+};
+
+/// Describe the window the renderer will draw into.
+#[derive(Debug, Clone)]
+pub struct WindowConfig {
+    title: String,
+    size: Option<[u32; 2]>,
+    resizable: bool,
+    fullscreen: bool,
+}
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: "Radiance cascades".to_owned(),
+            size: None,
+            resizable: true,
+            fullscreen: false,
+        }
+    }
+}
+impl WindowConfig {
+    pub fn title(self, title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            ..self
+        }
+    }
+
+    /// Initial inner size in physical pixels, the platform picks one when unset.
+    pub fn size(self, size: [u32; 2]) -> Self {
+        Self {
+            size: Some(size),
+            ..self
+        }
+    }
+
+    pub fn resizable(self, resizable: bool) -> Self {
+        Self { resizable, ..self }
+    }
+
+    /// Start in borderless fullscreen on the current monitor.
+    pub fn fullscreen(self, fullscreen: bool) -> Self {
+        Self { fullscreen, ..self }
+    }
+
+    pub fn attributes(&self) -> WindowAttributes {
+        let attributes = Window::default_attributes()
+            .with_title(self.title.clone())
+            .with_resizable(self.resizable)
+            .with_fullscreen(self.fullscreen.then_some(Fullscreen::Borderless(None)));
+
+        match self.size {
+            Some([width, height]) => attributes.with_inner_size(PhysicalSize::new(width, height)),
+            None => attributes,
+        }
+    }
+}
+
+/// Switch between windowed and borderless fullscreen.
+///
+/// The window will receive a resize event, the swapchain and viewport have to
+/// be recreated as for any other resize.
+pub fn toggle_fullscreen(window: &Window) {
+    let fullscreen = match window.fullscreen() {
+        Some(_) => None,
+        None => Some(Fullscreen::Borderless(None)),
+    };
+    window.set_fullscreen(fullscreen);
+}
+
+/// Whether a swapchain can be created for the window, which isn't the case
+/// while it is minimized or has a zero sized dimension.
+pub fn is_drawable(window: &Window) -> bool {
+    let size = window.inner_size();
+    size.width > 0 && size.height > 0 && window.is_minimized() != Some(true)
+}